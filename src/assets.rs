@@ -0,0 +1,90 @@
+use sdl2::image::ImageRWops;
+use sdl2::pixels::PixelFormatEnum;
+use sdl2::rect::Rect;
+use sdl2::render::{BlendMode, Canvas, Texture, TextureCreator};
+use sdl2::rwops::RWops;
+use sdl2::surface::Surface;
+use sdl2::video::{Window, WindowContext};
+use std::borrow::Cow;
+
+use crate::vehicle::VEHICLE_CLASSES;
+
+// Images and font of the program, built into the binary with the embed_assets feature
+// so it runs from any directory, read from the working directory otherwise
+#[cfg(feature = "embed_assets")]
+const SPRITES: [&[u8]; 7] = [
+    include_bytes!("../assets/red.png"),
+    include_bytes!("../assets/blue.png"),
+    include_bytes!("../assets/green.png"),
+    include_bytes!("../assets/yellow.png"),
+    include_bytes!("../assets/orange.png"),
+    include_bytes!("../assets/black.png"),
+    include_bytes!("../assets/white.png"),
+];
+#[cfg(feature = "embed_assets")]
+const FONT: &[u8] = include_bytes!("../font/RubikGlitch-Regular.ttf");
+#[cfg(not(feature = "embed_assets"))]
+const FONT_PATH: &str = "font/RubikGlitch-Regular.ttf";
+
+// PNG of a vehicle class, ordered like VEHICLE_CLASSES
+#[cfg(feature = "embed_assets")]
+fn sprite_data(class: usize) -> Result<Cow<'static, [u8]>, String> {
+    Ok(Cow::Borrowed(SPRITES[class]))
+}
+#[cfg(not(feature = "embed_assets"))]
+fn sprite_data(class: usize) -> Result<Cow<'static, [u8]>, String> {
+    let path = format!("assets/{}.png", VEHICLE_CLASSES[class]);
+    std::fs::read(&path).map(Cow::Owned).map_err(|e| format!("{}: {}", path, e))
+}
+
+// TTF font used by every text, read once and kept as long as the program
+#[cfg(feature = "embed_assets")]
+pub fn font_data() -> Result<&'static [u8], String> {
+    Ok(FONT)
+}
+#[cfg(not(feature = "embed_assets"))]
+pub fn font_data() -> Result<&'static [u8], String> {
+    let data = std::fs::read(FONT_PATH).map_err(|e| format!("{}: {}", FONT_PATH, e))?;
+    Ok(Box::leak(data.into_boxed_slice()))
+}
+
+// Sprites of every vehicle class side by side in one texture, loaded once
+pub struct TextureAtlas {
+    texture: Texture<'static>,
+    sprites: Vec<Rect>,     // area of each class in the texture, ordered like VEHICLE_CLASSES
+}
+impl TextureAtlas {
+    pub fn new(canvas: &Canvas<Window>) -> Result<Self, String> {
+        let mut images = Vec::new();
+        for class in 0..VEHICLE_CLASSES.len() {
+            let data = sprite_data(class)?;
+            let mut image = RWops::from_bytes(&data)?.load_png()?;
+            // copied as is into the atlas, alpha included
+            image.set_blend_mode(BlendMode::None)?;
+            images.push(image);
+        }
+        let width = images.iter().map(|i| i.width()).sum();
+        let height = images.iter().map(|i| i.height()).max().unwrap_or(1);
+        let mut atlas = Surface::new(width, height, PixelFormatEnum::RGBA8888)?;
+        let mut sprites = Vec::new();
+        let mut x = 0;
+        for image in &images {
+            let area = Rect::new(x, 0, image.width(), image.height());
+            image.blit(None, &mut atlas, area)?;
+            sprites.push(area);
+            x += image.width() as i32;
+        }
+        // The texture creator lives as long as the program so the atlas can be kept
+        let texture_creator: &'static TextureCreator<WindowContext> = Box::leak(Box::new(canvas.texture_creator()));
+        let mut texture = texture_creator
+            .create_texture_from_surface(&atlas)
+            .map_err(|e| e.to_string())?;
+        texture.set_blend_mode(BlendMode::Blend);
+        Ok(TextureAtlas { texture, sprites })
+    }
+
+    // Atlas texture and area of the sprite of a vehicle class
+    pub fn sprite(&self, class: usize) -> (&Texture<'static>, Rect) {
+        (&self.texture, self.sprites[class % self.sprites.len()])
+    }
+}
@@ -0,0 +1,108 @@
+use sdl2::rect::{Point, Rect};
+
+// Zoom limits, relative to the zoom showing the whole map
+const MIN_ZOOM: f64 = 0.5;
+const MAX_ZOOM: f64 = 10.0;
+
+// Part of the map shown in the window: the map point at the window centre and the zoom
+#[derive(Clone, Debug, PartialEq)]
+pub struct Camera {
+    map: Rect,
+    window: (u32, u32),     // size of the window in px
+    center: (f64, f64),     // map point shown at the centre of the window
+    zoom: f64,              // window px per map px
+}
+impl Camera {
+    pub fn new(map: Rect, window: (u32, u32)) -> Self {
+        let mut camera = Camera {
+            map,
+            window,
+            center: (0.0, 0.0),
+            zoom: 1.0,
+        };
+        camera.reset();
+        camera
+    }
+
+    // Zoom showing the whole map in the window
+    fn fit(&self) -> f64 {
+        (self.window.0 as f64 / self.map.width() as f64).min(self.window.1 as f64 / self.map.height() as f64)
+    }
+    // Show the whole map, centred
+    pub fn reset(&mut self) {
+        let center = self.map.center();
+        self.center = (center.x as f64, center.y as f64);
+        self.zoom = self.fit();
+    }
+    // Follow a window resize, keeping the centre and the zoom relative to the whole map
+    pub fn resize(&mut self, width: u32, height: u32) {
+        let relative = self.zoom / self.fit();
+        self.window = (width.max(1), height.max(1));
+        self.zoom = self.fit() * relative;
+    }
+
+    // Area of the map shown in the window
+    pub fn view(&self) -> Rect {
+        let (width, height) = (self.window.0 as f64 / self.zoom, self.window.1 as f64 / self.zoom);
+        Rect::new(
+            (self.center.0 - width / 2.0).round() as i32,
+            (self.center.1 - height / 2.0).round() as i32,
+            width.round().max(1.0) as u32,
+            height.round().max(1.0) as u32,
+        )
+    }
+    // Part of the map shown and the window area it is drawn on, the view clipped to the map so the
+    // image keeps its aspect ratio, None when the view misses the map
+    pub fn visible(&self) -> Option<(Rect, Rect)> {
+        let part = self.view().intersection(self.map)?;
+        let (left, top) = self.to_window_f(part.left() as f64, part.top() as f64);
+        let (right, bottom) = self.to_window_f(part.right() as f64, part.bottom() as f64);
+        let (left, top) = (left.round() as i32, top.round() as i32);
+        let window = Rect::new(
+            left,
+            top,
+            (right.round() as i32 - left).max(1) as u32,
+            (bottom.round() as i32 - top).max(1) as u32,
+        );
+        Some((part, window))
+    }
+    fn to_window_f(&self, x: f64, y: f64) -> (f64, f64) {
+        (
+            (x - self.center.0) * self.zoom + self.window.0 as f64 / 2.0,
+            (y - self.center.1) * self.zoom + self.window.1 as f64 / 2.0,
+        )
+    }
+    // Map point under a window point
+    pub fn to_map(&self, point: Point) -> Point {
+        let (x, y) = self.to_map_f(point);
+        Point::new(x.round() as i32, y.round() as i32)
+    }
+    fn to_map_f(&self, point: Point) -> (f64, f64) {
+        (
+            self.center.0 + (point.x as f64 - self.window.0 as f64 / 2.0) / self.zoom,
+            self.center.1 + (point.y as f64 - self.window.1 as f64 / 2.0) / self.zoom,
+        )
+    }
+
+    // Zoom by `factor` keeping the map point under the window point `point` in place
+    pub fn zoom_at(&mut self, point: Point, factor: f64) {
+        let before = self.to_map_f(point);
+        let fit = self.fit();
+        self.zoom = (self.zoom * factor).clamp(fit * MIN_ZOOM, fit * MAX_ZOOM);
+        let after = self.to_map_f(point);
+        self.center.0 += before.0 - after.0;
+        self.center.1 += before.1 - after.1;
+        self.keep_on_map();
+    }
+    // Move the view by a drag of (dx, dy) window px
+    pub fn pan(&mut self, dx: i32, dy: i32) {
+        self.center.0 -= dx as f64 / self.zoom;
+        self.center.1 -= dy as f64 / self.zoom;
+        self.keep_on_map();
+    }
+    // Keep the centre of the window over the map, so part of it is always shown
+    fn keep_on_map(&mut self) {
+        self.center.0 = self.center.0.clamp(self.map.left() as f64, self.map.right() as f64);
+        self.center.1 = self.center.1.clamp(self.map.top() as f64, self.map.bottom() as f64);
+    }
+}
@@ -0,0 +1,115 @@
+use sdl2::pixels::Color;
+use sdl2::rect::{Point, Rect};
+use sdl2::render::Canvas;
+use sdl2::ttf::Font;
+use sdl2::video::Window;
+
+use crate::overlay::draw_text;
+
+const AXIS_COLOR: Color = Color::RGB(175, 175, 175);
+const LABEL_COLOR: Color = Color::RGB(225, 225, 255);
+const MARGIN_LEFT: i32 = 50;   // room for the y labels
+const MARGIN_BOTTOM: i32 = 20; // room for the x labels
+
+// Plot area of a chart drawn in `area`, under the title and beside the labels
+fn plot_area(font: &Font, area: Rect) -> Rect {
+    let title = font.height();
+    Rect::new(
+        area.x() + MARGIN_LEFT,
+        area.y() + title,
+        area.width().saturating_sub(MARGIN_LEFT as u32 + 10),
+        area.height().saturating_sub((title + MARGIN_BOTTOM) as u32),
+    )
+}
+
+// Title, axes and y scale of a chart, returns the plot area
+fn draw_frame(canvas: &mut Canvas<Window>, font: &Font, area: Rect, title: &str, max_y: f64) -> Rect {
+    let plot = plot_area(font, area);
+    draw_text(canvas, font, title, area.x(), area.y(), LABEL_COLOR);
+    canvas.set_draw_color(AXIS_COLOR);
+    canvas.draw_line(plot.bottom_left(), Point::new(plot.right(), plot.bottom())).unwrap();
+    canvas.draw_line(plot.top_left(), plot.bottom_left()).unwrap();
+    draw_text(canvas, font, &format!("{:.1}", max_y), area.x(), plot.y(), LABEL_COLOR);
+    draw_text(canvas, font, "0", area.x(), plot.bottom() - font.height(), LABEL_COLOR);
+    plot
+}
+
+// Position in the plot area of a (x, y) point, both axes starting at 0
+fn to_plot(plot: Rect, max_x: f64, max_y: f64, (x, y): (f64, f64)) -> Point {
+    Point::new(
+        plot.x() + (x / max_x * plot.width() as f64) as i32,
+        plot.bottom() - (y / max_y * plot.height() as f64) as i32,
+    )
+}
+
+// Line chart of (x, y) points, both axes starting at 0
+pub fn draw_line_chart(canvas: &mut Canvas<Window>, font: &Font, area: Rect, title: &str, points: &[(f64, f64)], color: Color) {
+    let max_x = points.iter().map(|p| p.0).fold(0.0, f64::max);
+    let max_y = points.iter().map(|p| p.1).fold(0.0, f64::max);
+    let plot = draw_frame(canvas, font, area, title, max_y);
+    draw_text(canvas, font, &format!("{:.0}", max_x), plot.right() - 30, plot.bottom(), LABEL_COLOR);
+    if points.len() < 2 || max_x <= 0.0 {
+        draw_text(canvas, font, "no data", plot.x() + 10, plot.y() + 10, LABEL_COLOR);
+        return;
+    }
+    let max_y = if max_y > 0.0 { max_y } else { 1.0 };
+    let line: Vec<Point> = points.iter().map(|&p| to_plot(plot, max_x, max_y, p)).collect();
+    canvas.set_draw_color(color);
+    canvas.draw_lines(line.as_slice()).unwrap();
+}
+
+// Several lines of (x, y) points on the same axes, both starting at 0
+pub fn draw_lines_chart(canvas: &mut Canvas<Window>, font: &Font, area: Rect, title: &str, lines: &[Vec<(f64, f64)>], color: Color) {
+    let max_x = lines.iter().flatten().map(|p| p.0).fold(0.0, f64::max);
+    let max_y = lines.iter().flatten().map(|p| p.1).fold(0.0, f64::max);
+    let plot = draw_frame(canvas, font, area, title, max_y);
+    draw_text(canvas, font, &format!("{:.0}", max_x), plot.right() - 30, plot.bottom(), LABEL_COLOR);
+    if lines.iter().all(|l| l.len() < 2) || max_x <= 0.0 {
+        draw_text(canvas, font, "no data", plot.x() + 10, plot.y() + 10, LABEL_COLOR);
+        return;
+    }
+    let max_y = if max_y > 0.0 { max_y } else { 1.0 };
+    canvas.set_draw_color(color);
+    for points in lines.iter().filter(|l| l.len() >= 2) {
+        let line: Vec<Point> = points.iter().map(|&p| to_plot(plot, max_x, max_y, p)).collect();
+        canvas.draw_lines(line.as_slice()).unwrap();
+    }
+}
+
+// Bar chart with one labelled bar per value
+pub fn draw_bar_chart(canvas: &mut Canvas<Window>, font: &Font, area: Rect, title: &str, bars: &[(String, f64)], color: Color) {
+    let max_y = bars.iter().map(|b| b.1).fold(0.0, f64::max);
+    let plot = draw_frame(canvas, font, area, title, max_y);
+    if bars.is_empty() || max_y <= 0.0 {
+        draw_text(canvas, font, "no data", plot.x() + 10, plot.y() + 10, LABEL_COLOR);
+        return;
+    }
+    let slot = plot.width() / bars.len() as u32;
+    for (i, (label, value)) in bars.iter().enumerate() {
+        let height = (value / max_y * plot.height() as f64) as u32;
+        let x = plot.x() + (slot * i as u32) as i32;
+        canvas.set_draw_color(color);
+        canvas
+            .fill_rect(Rect::new(x + 2, plot.bottom() - height as i32, slot.saturating_sub(4).max(1), height.max(1)))
+            .unwrap();
+        draw_text(canvas, font, label, x + 2, plot.bottom(), LABEL_COLOR);
+    }
+}
+
+// Table of text cells, the first row is drawn as a header
+pub fn draw_table(canvas: &mut Canvas<Window>, font: &Font, x: i32, y: i32, column_width: i32, rows: &[Vec<String>]) {
+    let line_height = font.height() + 4;
+    for (i, row) in rows.iter().enumerate() {
+        let row_y = y + line_height * i as i32;
+        for (j, cell) in row.iter().enumerate() {
+            draw_text(canvas, font, cell, x + column_width * j as i32, row_y, LABEL_COLOR);
+        }
+        if i == 0 {
+            canvas.set_draw_color(AXIS_COLOR);
+            let width = column_width * row.len() as i32;
+            canvas
+                .draw_line(Point::new(x, row_y + line_height - 2), Point::new(x + width, row_y + line_height - 2))
+                .unwrap();
+        }
+    }
+}
@@ -0,0 +1,218 @@
+use std::time::Duration;
+
+use crate::demand::APPROACHES;
+use crate::layout::DrivingSide;
+use crate::network::{opposite, turned};
+use crate::vehicle::{StopReason, Vehicle};
+use crate::{Direction, Sector};
+
+// Rule deciding when a vehicle at the line of an arm enters the intersection box
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, Debug, PartialEq, Copy, Default)]
+pub enum Control {
+    #[default]
+    Heuristic,                          // hitboxes and the left and right priorities of `is_overlapping`
+    AllWayStop,                         // stop at the line on every arm, then first come first served
+    TwoWayStop { major: Direction },    // stop on the minor road, the road of `major` (an approach) has priority
+    PriorityToRight,                    // give way to the vehicles coming from the right (the left when driving on the left)
+}
+
+// Sign at the line of an arm
+#[derive(Clone, Debug, PartialEq, Copy)]
+pub enum Sign {
+    Stop,
+    PriorityRoad,
+    PriorityToRight,
+}
+
+impl Control {
+    // "heuristic", "all-way-stop", "two-way-stop" (major road north-south), "two-way-stop:ew", "priority-right"
+    pub fn parse(text: &str) -> Result<Self, String> {
+        match text {
+            "heuristic" => Ok(Control::Heuristic),
+            "all-way-stop" => Ok(Control::AllWayStop),
+            "two-way-stop" | "two-way-stop:ns" => Ok(Control::TwoWayStop { major: Direction::North }),
+            "two-way-stop:ew" => Ok(Control::TwoWayStop { major: Direction::East }),
+            "priority-right" => Ok(Control::PriorityToRight),
+            _ => Err(format!("unknown control: {}", text)),
+        }
+    }
+
+    // Whether the vehicles of `route` have to stop at the line
+    pub fn stops(&self, route: Direction) -> bool {
+        match self {
+            Control::AllWayStop => true,
+            Control::TwoWayStop { major } => !on_road(route, *major),
+            _ => false,
+        }
+    }
+    // Sign at the line of the arm of `route`
+    pub fn sign(&self, route: Direction) -> Option<Sign> {
+        match self {
+            Control::Heuristic => None,
+            Control::PriorityToRight => Some(Sign::PriorityToRight),
+            _ if self.stops(route) => Some(Sign::Stop),
+            _ => Some(Sign::PriorityRoad),
+        }
+    }
+
+    // Why a vehicle at the line has to wait before entering the box, None when it can go:
+    // it has not stopped yet, a vehicle it conflicts with is in the box, or one with priority is coming
+    pub fn must_wait(&self, vehicle: &Vehicle, vehicles: &[&Vehicle], sector: &Sector) -> Option<StopReason> {
+        if *self == Control::Heuristic || !at_line(vehicle, sector) {
+            return None;
+        }
+        if self.stops(vehicle.route) && vehicle.stopped_at.is_none() {
+            return Some(StopReason::StopLine);
+        }
+        let side = sector.layout.side;
+        let others: Vec<&Vehicle> = vehicles
+            .iter()
+            .copied()
+            .filter(|o| o.id() != vehicle.id() && o.body.has_intersection(sector.entry_intersect))
+            .filter(|o| conflicts(vehicle, o, side))
+            .collect();
+        if let Some(o) = others.iter().find(|o| o.body.has_intersection(sector.in_intersect)) {
+            return Some(StopReason::RightOfWay(o.id()));
+        }
+        // every arm waiting on the one on its right: the first vehicle goes
+        let all_arms = APPROACHES
+            .iter()
+            .all(|&r| vehicles.iter().any(|v| v.route == r && at_line(v, sector)));
+        others
+            .iter()
+            .find(|o| approaching(o, sector) && self.has_priority(o, vehicle, side, all_arms))
+            .map(|o| StopReason::RightOfWay(o.id()))
+    }
+
+    // Whether `other`, coming to the box and conflicting with `vehicle`, goes first
+    fn has_priority(&self, other: &Vehicle, vehicle: &Vehicle, side: DrivingSide, all_arms: bool) -> bool {
+        // first stopped at the line, then lower id
+        let first = |a: &Vehicle, b: &Vehicle| a.stopped_at.is_some() && at_line_order(a) < at_line_order(b);
+        // the vehicle crossing the oncoming traffic waits for the one going straight or along the kerb
+        let oncoming = other.route == opposite(vehicle.route)
+            && vehicle.direction == side.crossing_turn()
+            && other.direction != side.crossing_turn();
+        match self {
+            Control::AllWayStop => first(other, vehicle),
+            Control::TwoWayStop { major } => match (on_road(other.route, *major), on_road(vehicle.route, *major)) {
+                (true, false) => true,
+                (false, true) => false,
+                (false, false) => first(other, vehicle),
+                (true, true) => oncoming,
+            },
+            Control::PriorityToRight if other.route == turned(vehicle.route, side.crossing_turn()) => {
+                !all_arms || other.id() < vehicle.id()
+            }
+            Control::PriorityToRight => oncoming,
+            Control::Heuristic => false,
+        }
+    }
+}
+
+// Whether the closer hitbox of a vehicle reaches the box of its intersection while its body is still out of it
+pub fn at_line(vehicle: &Vehicle, sector: &Sector) -> bool {
+    vehicle.hitbox.closer.has_intersection(sector.in_intersect) && !vehicle.body.has_intersection(sector.in_intersect)
+}
+// Whether a vehicle drives toward the box of an intersection, not yet in it
+pub(crate) fn approaching(vehicle: &Vehicle, sector: &Sector) -> bool {
+    let area = sector.in_intersect;
+    !vehicle.body.has_intersection(area)
+        && match vehicle.route {
+            Direction::North => vehicle.position.y < area.top(),
+            Direction::South => vehicle.position.y > area.bottom(),
+            Direction::East => vehicle.position.x > area.right(),
+            Direction::West => vehicle.position.x < area.left(),
+            _ => false,
+        }
+}
+// Whether the paths of two vehicles cross in the box: any two arms but the same one,
+// or the opposite one when neither crosses the oncoming traffic
+pub(crate) fn conflicts(a: &Vehicle, b: &Vehicle, side: DrivingSide) -> bool {
+    if a.route == b.route {
+        return false;
+    }
+    a.route != opposite(b.route) || a.direction == side.crossing_turn() || b.direction == side.crossing_turn()
+}
+// Whether `route` drives on the road of the approach `major`
+fn on_road(route: Direction, major: Direction) -> bool {
+    route == major || route == opposite(major)
+}
+// Order of the vehicles at the lines: time of their stop, then id
+fn at_line_order(vehicle: &Vehicle) -> (Duration, u32) {
+    (vehicle.stopped_at.unwrap_or(Duration::MAX), vehicle.id())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vehicle(id: u32, route: Direction, direction: Direction, stopped_at: Option<u64>) -> Vehicle {
+        let mut vehicle = Vehicle::new(id, route, direction, 0, Duration::ZERO);
+        vehicle.stopped_at = stopped_at.map(Duration::from_secs);
+        vehicle
+    }
+
+    #[test]
+    fn priority_to_the_right() {
+        let control = Control::PriorityToRight;
+        let south_bound = vehicle(1, Direction::North, Direction::Forward, None);
+        // coming from the west is on the right of a vehicle coming from the north
+        let from_west = vehicle(2, Direction::West, Direction::Forward, None);
+        assert!(control.has_priority(&from_west, &south_bound, DrivingSide::Right, false));
+        assert!(!control.has_priority(&south_bound, &from_west, DrivingSide::Right, false));
+        // every arm waiting: the lower id goes first
+        assert!(!control.has_priority(&from_west, &south_bound, DrivingSide::Right, true));
+        // driving on the left, the priority is to the vehicle coming from the east
+        let from_east = vehicle(3, Direction::East, Direction::Forward, None);
+        assert!(control.has_priority(&from_east, &south_bound, DrivingSide::Left, false));
+        assert!(!control.has_priority(&from_west, &south_bound, DrivingSide::Left, false));
+        // crossing the oncoming traffic waits for the oncoming vehicle
+        let turning = vehicle(4, Direction::North, Direction::Left, None);
+        let oncoming = vehicle(5, Direction::South, Direction::Forward, None);
+        assert!(control.has_priority(&oncoming, &turning, DrivingSide::Right, false));
+        assert!(!control.has_priority(&turning, &oncoming, DrivingSide::Right, false));
+    }
+
+    #[test]
+    fn two_way_stop_precedence() {
+        let control = Control::TwoWayStop { major: Direction::North };
+        assert!(control.stops(Direction::East) && control.stops(Direction::West));
+        assert!(!control.stops(Direction::North) && !control.stops(Direction::South));
+        assert_eq!((control.sign(Direction::East), control.sign(Direction::South)), (Some(Sign::Stop), Some(Sign::PriorityRoad)));
+
+        let major = vehicle(1, Direction::South, Direction::Forward, None);
+        let minor = vehicle(2, Direction::East, Direction::Forward, Some(1));
+        assert!(control.has_priority(&major, &minor, DrivingSide::Right, false));
+        assert!(!control.has_priority(&minor, &major, DrivingSide::Right, false));
+        // on the minor road the first stopped goes first
+        let later = vehicle(3, Direction::West, Direction::Forward, Some(2));
+        assert!(control.has_priority(&minor, &later, DrivingSide::Right, false));
+        assert!(!control.has_priority(&later, &minor, DrivingSide::Right, false));
+        // on the major road, the vehicle crossing the oncoming traffic waits
+        let turning = vehicle(4, Direction::North, Direction::Left, None);
+        assert!(control.has_priority(&major, &turning, DrivingSide::Right, false));
+        assert!(!control.has_priority(&turning, &major, DrivingSide::Right, false));
+    }
+
+    #[test]
+    fn all_way_stop_first_come() {
+        let control = Control::AllWayStop;
+        let first = vehicle(5, Direction::North, Direction::Forward, Some(1));
+        let second = vehicle(2, Direction::East, Direction::Forward, Some(3));
+        let moving = vehicle(1, Direction::South, Direction::Left, None);
+        assert!(control.has_priority(&first, &second, DrivingSide::Right, false));
+        assert!(!control.has_priority(&second, &first, DrivingSide::Right, false));
+        assert!(!control.has_priority(&moving, &second, DrivingSide::Right, false));
+        // same stop time, lower id
+        let tie = vehicle(3, Direction::West, Direction::Forward, Some(3));
+        assert!(control.has_priority(&second, &tie, DrivingSide::Right, false));
+    }
+
+    #[test]
+    fn parse_controls() {
+        assert_eq!(Control::parse("two-way-stop:ew"), Ok(Control::TwoWayStop { major: Direction::East }));
+        assert_eq!(Control::parse("priority-right"), Ok(Control::PriorityToRight));
+        assert!(Control::parse("lights").is_err());
+    }
+}
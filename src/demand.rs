@@ -0,0 +1,279 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::time::Duration;
+
+use crate::scenario::{OdMatrix, TURNS};
+use crate::Direction;
+
+// Approaches a vehicle can spawn from, in the order used by per-approach arrays
+pub const APPROACHES: [Direction; 4] = [
+    Direction::North,
+    Direction::South,
+    Direction::East,
+    Direction::West,
+];
+
+// Index of an approach in the per-approach arrays, None for a turn
+pub fn approach_index(route: Direction) -> Option<usize> {
+    APPROACHES.iter().position(|&approach| approach == route)
+}
+
+// A vehicle to create: where it comes from and where it turns
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, Debug, PartialEq, Copy)]
+pub struct Spawn {
+    pub route: Direction,
+    pub direction: Direction,
+}
+
+// Source of the vehicles entering the simulation
+pub trait DemandGenerator {
+    // Vehicles created between `from` and `to` (time since the demand started)
+    fn generate(&mut self, from: Duration, to: Duration) -> Vec<Spawn>;
+    // Draw the vehicles from a random generator seeded with `seed`, to reproduce a run
+    // (the simulation reseeds it every tick from the seed of the run and the tick)
+    fn reseed(&mut self, seed: u64);
+}
+
+// One vehicle at a fixed interval, approach and turn drawn from the OD matrix
+pub struct FixedDemand {
+    interval: Duration,
+    od: OdMatrix,
+    rng: StdRng,
+}
+impl FixedDemand {
+    pub fn new(interval: Duration) -> Self {
+        FixedDemand {
+            interval,
+            od: OdMatrix::default(),
+            rng: StdRng::from_entropy(),
+        }
+    }
+    pub fn with_od(mut self, od: OdMatrix) -> Self {
+        self.od = od;
+        self
+    }
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.rng = StdRng::seed_from_u64(seed);
+        self
+    }
+}
+impl DemandGenerator for FixedDemand {
+    fn generate(&mut self, from: Duration, to: Duration) -> Vec<Spawn> {
+        let interval = self.interval.as_nanos().max(1);
+        // number of interval boundaries crossed in the window
+        let count = to.as_nanos() / interval - from.as_nanos() / interval;
+        (0..count)
+            .map(|_| {
+                let route = self.od.pick_approach(&mut self.rng);
+                let direction = self.od.pick_turn(route, &mut self.rng);
+                Spawn { route, direction }
+            })
+            .collect()
+    }
+    fn reseed(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+}
+
+// Multiplier applied to the base rate of one approach (or all of them) during a time window
+#[derive(Clone, Debug, PartialEq)]
+pub struct DemandPeriod {
+    pub start: Duration,
+    pub end: Duration,
+    pub approach: Option<Direction>,    // None applies to every approach
+    pub factor: f64,
+}
+
+// Time-of-day profile, periods overlapping the same approach multiply together
+#[derive(Clone, Debug, PartialEq, Default)]
+pub struct DemandProfile {
+    pub periods: Vec<DemandPeriod>,
+}
+impl DemandProfile {
+    // Constant demand
+    pub fn flat() -> Self {
+        DemandProfile::default()
+    }
+    // Morning peak: `approach` triples its demand during the middle half of `length`
+    pub fn am_peak(approach: Direction, length: Duration) -> Self {
+        DemandProfile {
+            periods: vec![DemandPeriod {
+                start: length / 4,
+                end: length * 3 / 4,
+                approach: Some(approach),
+                factor: 3.0,
+            }],
+        }
+    }
+    pub fn with_period(mut self, period: DemandPeriod) -> Self {
+        self.periods.push(period);
+        self
+    }
+    // Rate multiplier of `approach` at time `t`
+    pub fn factor(&self, approach: Direction, t: Duration) -> f64 {
+        self.periods
+            .iter()
+            .filter(|p| p.start <= t && t < p.end)
+            .filter(|p| p.approach.is_none() || p.approach == Some(approach))
+            .map(|p| p.factor)
+            .product()
+    }
+}
+
+// Independent Poisson arrivals on each cell of the OD matrix
+pub struct PoissonDemand {
+    od: OdMatrix,
+    profile: DemandProfile,
+    rng: StdRng,
+}
+impl PoissonDemand {
+    // Same rate (vehicles per minute) on every approach
+    pub fn new(rate: f64) -> Self {
+        PoissonDemand::with_rates([rate; 4])
+    }
+    // Rates in vehicles per minute for North, South, East and West, turns equally likely
+    pub fn with_rates(rates: [f64; 4]) -> Self {
+        PoissonDemand::from_od(OdMatrix::from_rates(rates))
+    }
+    // Flows of the OD matrix drive both the approach and the turn
+    pub fn from_od(od: OdMatrix) -> Self {
+        PoissonDemand {
+            od,
+            profile: DemandProfile::flat(),
+            rng: StdRng::from_entropy(),
+        }
+    }
+    pub fn with_profile(mut self, profile: DemandProfile) -> Self {
+        self.profile = profile;
+        self
+    }
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.rng = StdRng::seed_from_u64(seed);
+        self
+    }
+    // Draw the number of arrivals of a Poisson process with mean `lambda` (Knuth)
+    fn sample(&mut self, lambda: f64) -> u32 {
+        if lambda <= 0.0 {
+            return 0;
+        }
+        let limit = (-lambda).exp();
+        let mut count = 0;
+        let mut p: f64 = self.rng.gen();
+        while p > limit {
+            count += 1;
+            p *= self.rng.gen::<f64>();
+        }
+        count
+    }
+}
+impl DemandGenerator for PoissonDemand {
+    fn generate(&mut self, from: Duration, to: Duration) -> Vec<Spawn> {
+        let window = to.saturating_sub(from).as_secs_f64() / 60.0;
+        let mut spawns = Vec::new();
+        for route in APPROACHES {
+            let factor = self.profile.factor(route, from);
+            for direction in TURNS {
+                let lambda = self.od.flow(route, direction) * factor * window;
+                for _ in 0..self.sample(lambda) {
+                    spawns.push(Spawn { route, direction });
+                }
+            }
+        }
+        spawns
+    }
+    fn reseed(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Vehicles generated per approach over `minutes`, one tick at a time
+    fn arrivals(demand: &mut dyn DemandGenerator, minutes: u64) -> [usize; 4] {
+        let mut counts = [0; 4];
+        let tick = Duration::from_millis(10);
+        for i in 0..(minutes * 6000) as u32 {
+            for spawn in demand.generate(tick * i, tick * (i + 1)) {
+                counts[approach_index(spawn.route).unwrap()] += 1;
+            }
+        }
+        counts
+    }
+
+    #[test]
+    fn approach_indices() {
+        for (i, approach) in APPROACHES.into_iter().enumerate() {
+            assert_eq!(approach_index(approach), Some(i));
+        }
+        assert_eq!(approach_index(Direction::Left), None);
+    }
+
+    #[test]
+    fn poisson_mean() {
+        // 20 vehicles per minute on each approach during 30 minutes: 600 expected, a standard deviation of about 24
+        let counts = arrivals(&mut PoissonDemand::new(20.0).with_seed(3), 30);
+        for count in counts {
+            assert!((540..=660).contains(&count), "{} arrivals", count);
+        }
+        let counts = arrivals(&mut PoissonDemand::with_rates([0.0, 40.0, 0.0, 0.0]).with_seed(4), 30);
+        assert_eq!(counts[0] + counts[2] + counts[3], 0);
+        assert!((1110..=1290).contains(&counts[1]), "{} arrivals", counts[1]);
+    }
+
+    #[test]
+    fn profile_breakpoints() {
+        let minute = Duration::from_secs(60);
+        let profile = DemandProfile::am_peak(Direction::North, minute).with_period(DemandPeriod {
+            start: Duration::from_secs(40),
+            end: minute,
+            approach: None,
+            factor: 0.5,
+        });
+        let at = |ms: u64| Duration::from_millis(ms);
+        assert_eq!(profile.factor(Direction::North, at(14_999)), 1.0);
+        assert_eq!(profile.factor(Direction::North, at(15_000)), 3.0);
+        assert_eq!(profile.factor(Direction::South, at(15_000)), 1.0);
+        // overlapping periods multiply
+        assert_eq!(profile.factor(Direction::North, at(40_000)), 1.5);
+        assert_eq!(profile.factor(Direction::South, at(40_000)), 0.5);
+        assert_eq!(profile.factor(Direction::North, at(45_000)), 0.5);
+        assert_eq!(profile.factor(Direction::North, minute), 1.0);
+        assert_eq!(DemandProfile::flat().factor(Direction::East, at(1)), 1.0);
+    }
+
+    #[test]
+    fn poisson_follows_the_profile() {
+        // the north approach triples during the middle half of 20 minutes
+        let length = Duration::from_secs(20 * 60);
+        let mut demand = PoissonDemand::new(20.0).with_profile(DemandProfile::am_peak(Direction::North, length)).with_seed(5);
+        let (quarter, tick) = (length / 4, Duration::from_millis(10));
+        let mut north = [0; 4];
+        for i in 0..(length.as_millis() / 10) as u32 {
+            let from = tick * i;
+            let count = demand.generate(from, from + tick).iter().filter(|s| s.route == Direction::North).count();
+            north[(from.as_millis() / quarter.as_millis()) as usize] += count;
+        }
+        // 100 vehicles expected out of the peak quarters, 300 during them
+        for quarter in [north[0], north[3]] {
+            assert!((70..=130).contains(&quarter), "{} arrivals", quarter);
+        }
+        for quarter in [north[1], north[2]] {
+            assert!((250..=350).contains(&quarter), "{} arrivals", quarter);
+        }
+    }
+
+    #[test]
+    fn fixed_interval() {
+        let mut demand = FixedDemand::new(Duration::from_millis(300)).with_seed(1);
+        assert_eq!(demand.generate(Duration::ZERO, Duration::from_secs(3)).len(), 10);
+        assert_eq!(demand.generate(Duration::from_millis(250), Duration::from_millis(350)).len(), 1);
+        assert!(demand.generate(Duration::from_millis(310), Duration::from_millis(590)).is_empty());
+        // the same seed draws the same vehicles
+        let draw = |seed| FixedDemand::new(Duration::from_millis(300)).with_seed(seed).generate(Duration::ZERO, Duration::from_secs(30));
+        assert_eq!(draw(8), draw(8));
+        assert!(draw(8).iter().all(|s| approach_index(s.route).is_some() && s.direction != s.route));
+    }
+}
@@ -0,0 +1,119 @@
+use sdl2::pixels::Color;
+use sdl2::rect::{Point, Rect};
+use sdl2::render::Canvas;
+use sdl2::video::Window;
+use std::collections::HashMap;
+
+// Side of a heatmap cell in px
+pub const HEATMAP_CELL: u32 = 20;
+// A vehicle stopping in one tick from faster than this velocity makes a hard stop
+pub const HARD_STOP_VELOCITY: i32 = 1;
+
+// Kind of conflict accumulated by the heatmap
+#[derive(Clone, Debug, PartialEq, Copy)]
+pub enum Conflict {
+    CloseCall,
+    Collision,
+    HardStop,
+}
+impl Conflict {
+    // Weight of the conflict in the heat of a cell
+    fn weight(&self) -> u32 {
+        match self {
+            Conflict::CloseCall => 2,
+            Conflict::Collision => 5,
+            Conflict::HardStop => 1,
+        }
+    }
+}
+
+// Number of close calls, collisions and hard stops in one cell
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, Debug, PartialEq, Default, Copy)]
+pub struct CellCounts {
+    pub close_call: u32,
+    pub colision: u32,
+    pub hard_stop: u32,
+}
+impl CellCounts {
+    fn heat(&self) -> u32 {
+        self.close_call * Conflict::CloseCall.weight()
+            + self.colision * Conflict::Collision.weight()
+            + self.hard_stop * Conflict::HardStop.weight()
+    }
+}
+
+// Where the conflicts of the run happened, on a grid over the map
+// only the cells with a conflict are stored
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, Debug, PartialEq)]
+pub struct Heatmap {
+    #[cfg_attr(feature = "serde", serde(with = "crate::snapshot::rect"))]
+    map: Rect,
+    columns: u32,
+    cells: HashMap<u32, CellCounts>,
+}
+impl Heatmap {
+    pub fn new(map: Rect) -> Self {
+        Heatmap {
+            map,
+            columns: map.width().div_ceil(HEATMAP_CELL),
+            cells: HashMap::new(),
+        }
+    }
+
+    // Count a conflict at `position`, ignored outside the map
+    pub fn add(&mut self, position: Point, conflict: Conflict) {
+        if !self.map.contains_point(position) {
+            return;
+        }
+        let column = (position.x - self.map.x()) as u32 / HEATMAP_CELL;
+        let row = (position.y - self.map.y()) as u32 / HEATMAP_CELL;
+        let counts = self.cells.entry(row * self.columns + column).or_default();
+        match conflict {
+            Conflict::CloseCall => counts.close_call += 1,
+            Conflict::Collision => counts.colision += 1,
+            Conflict::HardStop => counts.hard_stop += 1,
+        }
+    }
+    // Total counts over the map
+    pub fn total(&self) -> CellCounts {
+        self.cells.values().fold(CellCounts::default(), |total, c| CellCounts {
+            close_call: total.close_call + c.close_call,
+            colision: total.colision + c.colision,
+            hard_stop: total.hard_stop + c.hard_stop,
+        })
+    }
+
+    // Draw the cells coloured from blue (few conflicts) to red (the most conflicts)
+    pub fn draw(&self, canvas: &mut Canvas<Window>) {
+        let max = self.cells.values().map(|c| c.heat()).max().unwrap_or(0);
+        if max == 0 {
+            return;
+        }
+        for (index, counts) in &self.cells {
+            let ratio = counts.heat() as f64 / max as f64;
+            let cell = Rect::new(
+                self.map.x() + ((index % self.columns) * HEATMAP_CELL) as i32,
+                self.map.y() + ((index / self.columns) * HEATMAP_CELL) as i32,
+                HEATMAP_CELL,
+                HEATMAP_CELL,
+            );
+            canvas.set_draw_color(heat_color(ratio));
+            canvas.fill_rect(cell).unwrap();
+        }
+    }
+}
+
+// Colour of a heat ratio (0 to 1): blue, then yellow, then red, more opaque as it heats
+fn heat_color(ratio: f64) -> Color {
+    let ratio = ratio.clamp(0.0, 1.0);
+    let alpha = (90.0 + 130.0 * ratio) as u8;
+    if ratio < 0.5 {
+        let t = ratio * 2.0;
+        Color::RGBA((255.0 * t) as u8, (255.0 * t) as u8, (255.0 * (1.0 - t)) as u8, alpha)
+    } else {
+        let t = (ratio - 0.5) * 2.0;
+        Color::RGBA(255, (255.0 * (1.0 - t)) as u8, 0, alpha)
+    }
+}
@@ -0,0 +1,383 @@
+use sdl2::rect::{Point, Rect};
+
+use crate::demand::{approach_index, APPROACHES};
+use crate::network::{opposite, turned};
+use crate::scenario::TURNS;
+use crate::vehicle::lane_point;
+use crate::{Direction, ROAD_NUMBER, ROAD_WIDTH};
+
+// Routes in the order of a quarter turn clockwise on the screen, the north arm being the reference
+const QUARTERS: [Direction; 4] = [Direction::North, Direction::East, Direction::South, Direction::West];
+
+// Side of the road vehicles drive on, the geometry and priority rules of Left are the mirror of Right
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, Debug, PartialEq, Copy, Default)]
+pub enum DrivingSide {
+    #[default]
+    Right,
+    Left,   // UK, Japan...
+}
+impl DrivingSide {
+    // Turn crossing the oncoming traffic, taken from the lanes next to the centre line
+    pub fn crossing_turn(self) -> Direction {
+        self.mirror(Direction::Left)
+    }
+    // Turn along the kerb
+    pub fn kerb_turn(self) -> Direction {
+        self.mirror(Direction::Right)
+    }
+    // Turn of right-hand traffic as seen in the mirror, Left and Right swapped when driving on the left
+    pub fn mirror(self, turn: Direction) -> Direction {
+        match (self, turn) {
+            (DrivingSide::Left, Direction::Left) => Direction::Right,
+            (DrivingSide::Left, Direction::Right) => Direction::Left,
+            _ => turn,
+        }
+    }
+    // Sign of the lateral coordinates of right-hand traffic
+    pub fn sign(self) -> i32 {
+        match self {
+            DrivingSide::Right => 1,
+            DrivingSide::Left => -1,
+        }
+    }
+}
+
+// One arm of an intersection: the turns allowed on each lane toward the intersection,
+// from the lane next to the centre line to the kerb, and the number of lanes leaving it
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, Debug, PartialEq)]
+pub struct Arm {
+    pub lanes: Vec<Vec<Direction>>,
+    pub exits: usize,
+}
+impl Default for Arm {
+    // A lane per turn: Left, Forward, Right
+    fn default() -> Self {
+        Arm::new(TURNS.iter().map(|&turn| vec![turn]).collect(), ROAD_NUMBER as usize)
+    }
+}
+impl Arm {
+    pub fn new(lanes: Vec<Vec<Direction>>, exits: usize) -> Self {
+        assert!(
+            lanes.len() <= ROAD_NUMBER as usize && exits <= ROAD_NUMBER as usize,
+            "an arm has at most {} lanes each way",
+            ROAD_NUMBER
+        );
+        Arm { lanes, exits }
+    }
+}
+
+// Where a vehicle on a lane turns, and the route it takes
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, Debug, PartialEq, Copy)]
+pub struct TurnPoint {
+    pub route: Direction,
+    pub turn: Direction,
+    pub lane: usize,
+    #[cfg_attr(feature = "serde", serde(with = "crate::snapshot::point"))]
+    pub point: Point,
+    pub to: Direction,
+    #[cfg_attr(feature = "serde", serde(with = "crate::snapshot::rect"))]
+    pub area: Rect,     // lane square around the point
+}
+
+// Road meeting an imported junction: the route of the vehicles coming to it on that road, and its lanes each way
+#[derive(Clone, Debug, PartialEq, Copy)]
+pub struct ArmRoad {
+    pub route: Direction,
+    pub lanes_in: u32,      // toward the junction, 0 on a one-way road leaving it
+    pub lanes_out: u32,     // away from it, 0 on a one-way road coming to it
+}
+
+// Arms of an intersection, ordered like APPROACHES, None where there is no road
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, Debug, PartialEq)]
+pub struct Layout {
+    pub arms: [Option<Arm>; 4],
+    pub side: DrivingSide,
+}
+impl Default for Layout {
+    fn default() -> Self {
+        Layout::cross()
+    }
+}
+impl Layout {
+    // Four identical arms, a lane per turn
+    pub fn cross() -> Self {
+        Layout {
+            arms: [Some(Arm::default()), Some(Arm::default()), Some(Arm::default()), Some(Arm::default())],
+            side: DrivingSide::Right,
+        }
+    }
+    // Three arms, without the one of `missing`: a lane per turn still possible
+    pub fn t_junction(missing: Direction) -> Self {
+        let mut layout = Layout::cross().without_arm(missing);
+        for route in APPROACHES.into_iter().filter(|&r| r != missing) {
+            let lanes = TURNS
+                .into_iter()
+                .filter(|&turn| exit_arm(route, turn) != missing)
+                .map(|turn| vec![turn])
+                .collect();
+            layout = layout.with_arm(route, Arm::new(lanes, ROAD_NUMBER as usize));
+        }
+        layout
+    }
+    // Arms of the roads meeting at a junction, the widest road of a side giving its lanes (at most ROAD_NUMBER):
+    // the turns toward the arms with lanes leaving the junction are spread over the lanes of each arm,
+    // a lane per turn when the numbers match, otherwise shared by neighbour lanes or turns
+    pub fn from_roads(roads: &[ArmRoad]) -> Self {
+        let mut lanes: [Option<(usize, usize)>; 4] = [None; 4];
+        for road in roads {
+            let Some(i) = approach_index(road.route) else { continue };
+            let side = lanes[i].get_or_insert((0, 0));
+            side.0 = side.0.max(road.lanes_in.min(ROAD_NUMBER) as usize);
+            side.1 = side.1.max(road.lanes_out.min(ROAD_NUMBER) as usize);
+        }
+        let exits = |route: Direction| approach_index(route).and_then(|i| lanes[i]).map_or(0, |side| side.1);
+        let mut layout = Layout { arms: [None, None, None, None], side: DrivingSide::Right };
+        for (index, route) in APPROACHES.into_iter().enumerate() {
+            let Some((lanes_in, lanes_out)) = lanes[index] else { continue };
+            let turns: Vec<Direction> = TURNS.into_iter().filter(|&turn| exits(exit_arm(route, turn)) > 0).collect();
+            // lane `i` of `n` takes the turns i to i + turns - n, or a single one when there are more lanes than turns,
+            // going straight on only toward a lane in front
+            let window = |n: usize, i: usize| {
+                if n > turns.len() {
+                    &turns[i * turns.len() / n..][..1]
+                } else {
+                    &turns[i..i + turns.len() - n + 1]
+                }
+            };
+            let forward = exits(exit_arm(route, Direction::Forward));
+            let count = match turns.len() {
+                0 => 0,
+                _ => (1..=lanes_in)
+                    .rev()
+                    .find(|&n| (0..n).all(|i| i < forward || !window(n, i).contains(&Direction::Forward)))
+                    .unwrap_or(0),
+            };
+            let arm = Arm::new((0..count).map(|i| window(count, i).to_vec()).collect(), lanes_out);
+            layout.arms[index] = Some(arm);
+        }
+        layout
+    }
+    // Drive on the other side: the lanes keep their place from the centre line, their Left and Right turns are swapped
+    pub fn with_side(mut self, side: DrivingSide) -> Self {
+        if side != self.side {
+            for arm in self.arms.iter_mut().flatten() {
+                for turns in &mut arm.lanes {
+                    for turn in turns.iter_mut() {
+                        *turn = DrivingSide::Left.mirror(*turn);
+                    }
+                }
+            }
+            self.side = side;
+        }
+        self
+    }
+    // Replace the arm of an approach, a turn has no arm and leaves the layout unchanged
+    pub fn with_arm(mut self, approach: Direction, arm: Arm) -> Self {
+        if let Some(i) = approach_index(approach) {
+            self.arms[i] = Some(arm);
+        }
+        self
+    }
+    // Remove the arm of an approach
+    pub fn without_arm(mut self, approach: Direction) -> Self {
+        if let Some(i) = approach_index(approach) {
+            self.arms[i] = None;
+        }
+        self
+    }
+
+    pub fn arm(&self, route: Direction) -> Option<&Arm> {
+        approach_index(route).and_then(|i| self.arms[i].as_ref())
+    }
+    // Lanes of the arm of `route` where `turn` is allowed, from the centre line
+    pub fn lanes_for(&self, route: Direction, turn: Direction) -> Vec<usize> {
+        let Some(arm) = self.arm(route) else { return Vec::new() };
+        if self.arm(exit_arm(route, turn)).is_none_or(|exit| exit.exits == 0) {
+            return Vec::new();
+        }
+        (0..arm.lanes.len()).filter(|&lane| arm.lanes[lane].contains(&turn)).collect()
+    }
+    pub fn allows(&self, route: Direction, turn: Direction) -> bool {
+        !self.lanes_for(route, turn).is_empty()
+    }
+    // Turns allowed on a lane
+    pub fn turns_of(&self, route: Direction, lane: usize) -> Vec<Direction> {
+        self.arm(route)
+            .and_then(|arm| arm.lanes.get(lane))
+            .map(|turns| turns.iter().copied().filter(|&turn| self.allows(route, turn)).collect())
+            .unwrap_or_default()
+    }
+
+    // Panics when a lane going straight on has no lane in front of it
+    pub fn check(&self) {
+        for route in APPROACHES {
+            for lane in self.lanes_for(route, Direction::Forward) {
+                let exits = self.arm(exit_arm(route, Direction::Forward)).map_or(0, |arm| arm.exits);
+                assert!(lane < exits, "lane {} of {:?} goes straight on to no lane", lane, route);
+            }
+        }
+    }
+
+    // Turn points of every turning lane of an intersection centred on `center`: turns crossing the oncoming traffic
+    // go from the centre line to the lanes next to it, turns along the kerb from the kerb to the kerb lanes
+    pub fn turn_points(&self, center: Point) -> Vec<TurnPoint> {
+        let size = ROAD_WIDTH / (ROAD_NUMBER * 2);
+        let mut points = Vec::new();
+        for route in APPROACHES {
+            for turn in [self.side.crossing_turn(), self.side.kerb_turn()] {
+                let lanes = self.lanes_for(route, turn);
+                let Some(exit) = self.arm(exit_arm(route, turn)) else { continue };
+                let to = turned(route, turn);
+                for (i, &lane) in lanes.iter().enumerate() {
+                    let target = if turn == self.side.crossing_turn() {
+                        i.min(exit.exits - 1)
+                    } else {
+                        exit.exits.saturating_sub(lanes.len() - i)
+                    };
+                    let (from, into) = (
+                        lane_point(center, route, lane, self.side, center),
+                        lane_point(center, to, target, self.side, center),
+                    );
+                    let point = match route {
+                        Direction::North | Direction::South => Point::new(from.x, into.y),
+                        _ => Point::new(into.x, from.y),
+                    };
+                    points.push(TurnPoint {
+                        route,
+                        turn,
+                        lane,
+                        point,
+                        to,
+                        area: Rect::from_center(point, size, size),
+                    });
+                }
+            }
+        }
+        points
+    }
+}
+
+// Arm where a vehicle coming from `route` and taking `turn` leaves the intersection
+pub fn exit_arm(route: Direction, turn: Direction) -> Direction {
+    opposite(turned(route, turn))
+}
+
+// Point given for the north arm of an intersection centred on `center`, turned to the arm of `route`
+pub fn to_map(center: Point, (x, y): (i32, i32), route: Direction) -> Point {
+    let (mut x, mut y) = (x, y);
+    for _ in 0..quarter_of(route) {
+        (x, y) = (-y, x);
+    }
+    Point::new(center.x + x, center.y + y)
+}
+// Point of the arm of `route` seen from the north arm
+pub fn to_local(center: Point, point: Point, route: Direction) -> (i32, i32) {
+    let (mut x, mut y) = (point.x - center.x, point.y - center.y);
+    for _ in 0..quarter_of(route) {
+        (x, y) = (y, -x);
+    }
+    (x, y)
+}
+// Rectangle between two corners given for the north arm, turned to the arm of `route`
+pub fn rect_to_map(center: Point, a: (i32, i32), b: (i32, i32), route: Direction) -> Rect {
+    let (a, b) = (to_map(center, a, route), to_map(center, b, route));
+    Rect::new(a.x.min(b.x), a.y.min(b.y), (a.x - b.x).unsigned_abs(), (a.y - b.y).unsigned_abs())
+}
+// Route of the arm reached by turning the arm of `from` like the north arm was turned to `by`
+pub fn rotate_route(from: Direction, by: Direction) -> Direction {
+    QUARTERS[(quarter_of(from) + quarter_of(by)) % 4]
+}
+fn quarter_of(route: Direction) -> usize {
+    QUARTERS.iter().position(|&r| r == route).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cross_lanes_and_turn_points() {
+        let layout = Layout::cross();
+        let lanes: Vec<Vec<usize>> = TURNS.into_iter().map(|turn| layout.lanes_for(Direction::North, turn)).collect();
+        assert_eq!(lanes, vec![vec![0], vec![1], vec![2]]);
+        assert_eq!(layout.turns_of(Direction::East, 2), vec![Direction::Right]);
+
+        let center = Point::new(500, 500);
+        let points = layout.turn_points(center);
+        // a left and a right turning lane on every arm
+        assert_eq!(points.len(), 8);
+        let at = |route, turn| *points.iter().find(|p| p.route == route && p.turn == turn).unwrap();
+        let (near, far) = (lane_point(center, Direction::North, 0, DrivingSide::Right, center), lane_point(center, Direction::North, 2, DrivingSide::Right, center));
+        // from the north, left to the lane next to the centre line going east, right to the kerb lane going west
+        let left = at(Direction::North, Direction::Left);
+        assert_eq!((left.lane, left.to, left.point), (0, Direction::West, Point::new(near.x, center.y + (center.x - near.x))));
+        let right = at(Direction::North, Direction::Right);
+        assert_eq!((right.lane, right.to, right.point), (2, Direction::East, Point::new(far.x, center.y - (center.x - far.x))));
+    }
+
+    #[test]
+    fn t_junction_lanes() {
+        let layout = Layout::t_junction(Direction::North);
+        assert!(layout.arm(Direction::North).is_none());
+        assert_eq!(layout.arm(Direction::South).unwrap().lanes, vec![vec![Direction::Left], vec![Direction::Right]]);
+        assert!(!layout.allows(Direction::East, Direction::Right));
+        assert_eq!(layout.lanes_for(Direction::West, Direction::Right), vec![1]);
+        // nothing turns toward the missing arm
+        let points = layout.turn_points(Point::new(500, 500));
+        assert!(points.iter().all(|p| exit_arm(p.route, p.turn) != Direction::North && p.route != Direction::North));
+        assert_eq!(points.len(), 4);
+        layout.check();
+    }
+
+    #[test]
+    fn lanes_from_roads() {
+        let road = |route, lanes_in, lanes_out| ArmRoad { route, lanes_in, lanes_out };
+        let roads = [
+            road(Direction::North, 2, 2),
+            road(Direction::South, 2, 2),
+            road(Direction::East, 5, 1),
+            // one-way road leaving the junction
+            road(Direction::West, 0, 1),
+        ];
+        let layout = Layout::from_roads(&roads);
+        // the turns shared by neighbour lanes, straight on from the lanes with a lane in front
+        let north = layout.arm(Direction::North).unwrap();
+        assert_eq!(north.lanes, vec![vec![Direction::Left, Direction::Forward], vec![Direction::Forward, Direction::Right]]);
+        // a single lane in front: the lanes which would go straight on to nowhere are merged
+        let east = layout.arm(Direction::East).unwrap();
+        assert_eq!(east.lanes, vec![vec![Direction::Left, Direction::Forward, Direction::Right]]);
+        assert!(layout.arm(Direction::West).unwrap().lanes.is_empty());
+        assert_eq!(layout.lanes_for(Direction::South, Direction::Right), vec![1]);
+        layout.check();
+    }
+
+    #[test]
+    fn left_hand_mirrors_the_turn_points() {
+        let center = Point::new(500, 500);
+        let left = Layout::cross().with_side(DrivingSide::Left);
+        // the lanes keep their place, the crossing turn is next to the centre line
+        assert_eq!(left.lanes_for(Direction::North, Direction::Right), vec![0]);
+        assert_eq!(left.lanes_for(Direction::North, Direction::Left), vec![2]);
+        // every turn point is the one of right-hand traffic seen in a mirror along the north-south axis
+        let flip = |route| match route {
+            Direction::East => Direction::West,
+            Direction::West => Direction::East,
+            _ => route,
+        };
+        let mut mirrored: Vec<(Direction, Direction, usize, Direction, Point)> = Layout::cross()
+            .turn_points(center)
+            .into_iter()
+            .map(|p| (flip(p.route), DrivingSide::Left.mirror(p.turn), p.lane, flip(p.to), Point::new(2 * center.x - p.point.x, p.point.y)))
+            .collect();
+        let mut points: Vec<(Direction, Direction, usize, Direction, Point)> =
+            left.turn_points(center).into_iter().map(|p| (p.route, p.turn, p.lane, p.to, p.point)).collect();
+        let key = |p: &(Direction, Direction, usize, Direction, Point)| (p.4.x, p.4.y, p.2);
+        mirrored.sort_by_key(key);
+        points.sort_by_key(key);
+        assert_eq!(points, mirrored);
+    }
+}
@@ -1,26 +1,55 @@
 extern crate sdl2;
 
-use sdl2::event::Event;
+use sdl2::event::{Event, WindowEvent};
 use sdl2::keyboard::Keycode;
-use sdl2::pixels::Color;
+use sdl2::pixels::{Color, PixelFormatEnum};
 use sdl2::rect::{Point, Rect};
-use sdl2::image::{LoadTexture};
+use sdl2::rwops::RWops;
+use sdl2::mouse::MouseButton;
 use sdl2::ttf::Font;
 use sdl2::render::TextureQuery;
 
-use sdl2::render::Canvas;
-use sdl2::video::Window;
+use sdl2::render::{Canvas, Texture, TextureCreator};
+use sdl2::video::{Window, WindowContext};
 use sdl2::EventPump;
 
-use rand::Rng;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 use std::collections::VecDeque;
-use std::thread::spawn;
 use std::time::{Duration, Instant};
 
 pub mod vehicle;
 pub mod sector;
+pub mod demand;
+pub mod scenario;
+pub mod queue;
+pub mod replay;
+pub mod world;
+pub mod overlay;
+pub mod chart;
+pub mod report;
+pub mod safety;
+pub mod heatmap;
+pub mod trajectory;
+pub mod assets;
+pub mod camera;
+pub mod network;
+pub mod roundabout;
+pub mod layout;
+pub mod control;
+pub mod platoon;
+pub mod xml;
+pub mod osm;
+pub mod sumo;
+#[cfg(feature = "serde")]
+pub mod snapshot;
 use vehicle::*;
 use sector::*;
+use demand::*;
+use scenario::*;
+use replay::*;
+use report::StatsSummary;
+use world::*;
 
 
 // Constants for the simulation window, road dimensions, vehicle size, and safe distance between vehicles
@@ -33,8 +62,36 @@ const VEHICLE_WIDTH: u32 = 25;
 const VEHICLE_HEIGHT: u32 = 50;
 const SAFE_DISTANCE: u32 = 20; 
 const NUMBER_AV:usize = 24;
+// Summary, charts and breakdown tables
+const STAT_PAGES: usize = 4;
+const STATS_PATH: &str = "stats.csv";
+const TOTALS_PATH: &str = "totals.csv";
+const LOS_PATH: &str = "los.csv";
+const SAFETY_PATH: &str = "safety.csv";
+const FCD_PATH: &str = "fcd.xml";
+const HEATMAP_PATH: &str = "heatmap.png";
+// Ticks between two captured frames, 25 frames per simulated second
+const CAPTURE_TICKS: u32 = 4;
+// Longest simulated time captured by default, a gridlock or a collision lock would never empty the map
+pub const CAPTURE_MAX: Duration = Duration::from_secs(600);
+// Zoom factor of one mouse wheel step
+const ZOOM_STEP: f64 = 1.2;
+
+// Simulated time of one update, and playback jump of the scrub keys
+pub const TICK: Duration = Duration::from_millis(10);
+const SCRUB_TICKS: u32 = 500;
+// Ticks run by the step-N key and rewound by the long rewind key, and length of the rewind history
+const STEP_TICKS: u32 = 100;
+const REWIND_TICKS: usize = 1000;
+// Ticks between two states kept for rewinding, the ticks in between are simulated again
+const CHECKPOINT_TICKS: u32 = 100;
+// Ticks the statistics shown by the HUD are kept before being summarised again
+const SUMMARY_TICKS: u32 = 100;
+#[cfg(feature = "serde")]
+const SNAPSHOT_PATH: &str = "snapshot.json";
 
 // Enum to represent the direction of vehicle movement
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[derive(Clone, Debug, PartialEq, Copy)]
 pub enum Direction {
     North,
@@ -46,6 +103,7 @@ pub enum Direction {
     Forward,
 }
 // Enum to represent the direction of vehicle movement
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[derive(Clone, Debug, PartialEq, Copy)]
 pub enum Velocity {
     Stop,
@@ -53,76 +111,287 @@ pub enum Velocity {
     Normal,
     Fast,
 }
-// Main simulation struct, which contains SDL canvas, event pump, a queue of vehicles, traffic lights, and random number generator
+// Main simulation struct, which contains SDL canvas, event pump, the simulated world and random number generator
 pub struct Simulation {
     canvas: Canvas<Window>,
     event_pump: EventPump,
-    vehicles: VecDeque<Vehicle>,
-    sector: Sector,
-    stats: VecDeque<Stats>,
-    rng: rand::rngs::ThreadRng,
+    font: Font<'static, 'static>,
+    title_font: Font<'static, 'static>,    // larger font of the summary page
+    atlas: assets::TextureAtlas,            // sprites of the vehicles
+    view: Texture<'static>,                 // the whole map, drawn before the camera picks the part shown
+    view_scale: f32,                        // view px per map px, below 1 when the map is larger than the largest texture
+    texture_creator: &'static TextureCreator<WindowContext>,
+    camera: camera::Camera,
+    world: World,
+    rng: StdRng,    // turns of the vehicles spawned with the keyboard
     // to limit the spawn of the vehicles
     last_spawn_time: Instant,
     cooldown: Duration,
     refresh_time: Duration,
     speed_boost: u32,
     visibility: (bool,bool),
+    selected: Option<u32>,  // id of the inspected vehicle
+    hud: bool,
+    heatmap: bool,  // show where the conflicts happened
+    trails: bool,   // draw the recent positions behind the vehicles
+    trajectories: trajectory::Trajectories,
+    // real duration of a frame, smoothed, to show the time multiplier
+    last_frame: Instant,
+    frame_time: f64,
     paused: bool,
+    step: u32,      // ticks left to run while paused
+    // recent states for rewinding, one every CHECKPOINT_TICKS
+    history: VecDeque<Checkpoint>,
     stat_showing: bool,
-    spawn_loop: (Instant,Duration),
+    stat_page: usize,   // page of the statistics screen
+    summary: Option<(u32, StatsSummary)>,   // statistics of the world and the tick they were summarised at
+    scenario: Scenario,
+    demand: Box<dyn DemandGenerator>,
+    // start and length of the auto-spawn loop, and demand time already generated
+    spawn_loop: (Duration,Duration),
+    demand_clock: Duration,
+    // run log being written, or being played back
+    recorder: Option<Recorder>,
+    player: Option<Player>,
+    // frames written to PNGs
+    capture: Option<Capture>,
+}
+// State of the world kept for rewinding, with the vehicles spawned after it
+struct Checkpoint {
+    world: World,
+    demand_clock: Duration,
+    spawns: Vec<(u32, Direction, Direction)>,  // tick, route and turn
+}
+// Playback of a run log
+struct Player {
+    replay: Replay,
+    cursor: usize,  // next spawn event to apply
+}
+// Numbered PNGs written at a fixed simulated frame rate
+struct Capture {
+    dir: std::path::PathBuf,
+    frame: u32,             // number of the next PNG
+    last_tick: Option<u32>, // tick of the last captured frame
+    max: Duration,          // simulated time after which the capture stops
+}
+impl Capture {
+    // Write the frame drawn on the canvas if its tick is due
+    fn frame(&mut self, canvas: &Canvas<Window>, tick: u32) -> Result<(), String> {
+        if !tick.is_multiple_of(CAPTURE_TICKS) || self.last_tick == Some(tick) {
+            return Ok(());
+        }
+        let path = self.dir.join(format!("frame_{:06}.png", self.frame));
+        overlay::save_png(canvas, &path.to_string_lossy())?;
+        self.frame += 1;
+        self.last_tick = Some(tick);
+        Ok(())
+    }
 }
 impl Simulation {
     // Initialize a new simulation
     pub fn new() -> Self {
+        Simulation::create(false)
+    }
+    // Initialize a simulation rendered off-screen, to capture frames without a display
+    pub fn headless() -> Self {
+        // the offscreen driver needs no display, unless another driver was asked for
+        if std::env::var_os("SDL_VIDEODRIVER").is_none() {
+            sdl2::hint::set("SDL_VIDEODRIVER", "offscreen");
+        }
+        Simulation::create(true)
+    }
+    fn create(headless: bool) -> Self {
         // Initialize SDL context and video subsystem
         let sdl_context = sdl2::init().unwrap();
         let video_subsystem = sdl_context.video().unwrap();
 
         // Create a centered window with specified width and height
-        let window = video_subsystem
-            .window("Traffic Simulation", SCREEN_WIDTH, SCREEN_HEIGHT)
-            .position_centered()
-            .build()
-            .unwrap();
-
-        // Create a canvas for rendering
-        let canvas = window.into_canvas().build().unwrap();
-        let event_pump = sdl_context.event_pump().unwrap();
-        let rng = rand::thread_rng();
+        let mut window = video_subsystem.window("Traffic Simulation", SCREEN_WIDTH, SCREEN_HEIGHT);
+        window.position_centered();
+        if headless {
+            window.hidden();
+        } else {
+            window.resizable();
+        }
+        let window = window.build().unwrap();
 
-        let map = Rect::new(0, 0, SCREEN_WIDTH, SCREEN_HEIGHT);
-        let sector = Sector::new(map);
+        // Create a canvas for rendering, in software when headless
+        let mut canvas = window.into_canvas();
+        if headless {
+            canvas = canvas.software();
+        }
+        let canvas = canvas.build().unwrap();
+        let event_pump = sdl_context.event_pump().unwrap();
+        // The TTF context lives as long as the program so the font can be kept
+        let ttf_context = Box::leak(Box::new(sdl2::ttf::init().expect("Failed to initialize TTF context")));
+        let font_data = assets::font_data().expect("Failed to load font");
+        let font = ttf_context
+            .load_font_from_rwops(RWops::from_bytes(font_data).unwrap(), 16)
+            .expect("Failed to load font");
+        let title_font = ttf_context
+            .load_font_from_rwops(RWops::from_bytes(font_data).unwrap(), 26)
+            .expect("Failed to load font");
+        let atlas = assets::TextureAtlas::new(&canvas).expect("Failed to load vehicle sprites");
+        let seed = rand::thread_rng().gen();
+        let world = World::new(seed);
+        let rng = StdRng::seed_from_u64(keys_seed(seed));
+        // The texture creator lives as long as the program so the map texture can be kept
+        let texture_creator: &'static TextureCreator<WindowContext> = Box::leak(Box::new(canvas.texture_creator()));
+        let map = world.network.map;
+        let (view, view_scale) = map_texture(&canvas, texture_creator, map);
+        let camera = camera::Camera::new(map, canvas.output_size().unwrap());
 
         // Return an instance of the Simulation struct
         Simulation {
             canvas,
             event_pump,
-            vehicles: VecDeque::new(),
-            sector,
-            stats: VecDeque::new(),
+            font,
+            title_font,
+            atlas,
+            view,
+            view_scale,
+            texture_creator,
+            camera,
+            world,
             rng,
             last_spawn_time: Instant::now(),
             cooldown: Duration::from_millis(300),
-            refresh_time: Duration::from_millis(10),
+            refresh_time: TICK,
             speed_boost: 0,
             visibility: (false,false),
+            selected: None,
+            hud: true,
+            heatmap: false,
+            trails: false,
+            trajectories: trajectory::Trajectories::new(),
+            last_frame: Instant::now(),
+            frame_time: 0.0,
             paused: false,
+            step: 0,
+            history: VecDeque::new(),
             stat_showing: false,
-            spawn_loop: (Instant::now(),Duration::from_secs_f32(0.0)),
+            stat_page: 0,
+            summary: None,
+            scenario: Scenario::default(),
+            demand: Box::new(FixedDemand::new(Duration::from_millis(300))),
+            spawn_loop: (Duration::ZERO,Duration::ZERO),
+            demand_clock: Duration::ZERO,
+            recorder: None,
+            player: None,
+            capture: None,
+        }
+    }
+    // Replace the demand used by the auto-spawn loop
+    pub fn with_demand(mut self, demand: Box<dyn DemandGenerator>) -> Self {
+        self.demand = demand;
+        self
+    }
+    // Use the turning flows and loop length of a scenario, generated demand follows its OD matrix
+    pub fn with_scenario(mut self, scenario: Scenario) -> Self {
+        self.demand = Box::new(PoissonDemand::from_od(scenario.od.clone()));
+        self.scenario = scenario;
+        self.world = self.new_world(self.world.seed);
+        self.fit_view();
+        self
+    }
+    // Thresholds of the time-to-collision, post-encroachment time and deceleration conflicts
+    pub fn with_safety(mut self, config: safety::SafetyConfig) -> Self {
+        self.scenario.safety = config;
+        self.world.safety.config = config;
+        self
+    }
+    // Simulate a network of intersections, restarting from an empty world
+    pub fn with_network(mut self, network: network::Network) -> Self {
+        self.scenario.network = network;
+        self.world = self.new_world(self.world.seed);
+        self.fit_view();
+        self
+    }
+    // Restart from an empty world using the given seed, which also draws the demand and the keyboard turns
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.world = self.new_world(seed);
+        self.rng = StdRng::seed_from_u64(keys_seed(seed));
+        self
+    }
+    // Let the connected vehicles form platoons through the intersections
+    pub fn with_platooning(mut self, config: platoon::PlatoonConfig) -> Self {
+        self.scenario.platooning = Some(config);
+        self.world.platooning = Some(config);
+        self
+    }
+    // Empty world of the scenario network, safety thresholds and platooning
+    fn new_world(&self, seed: u64) -> World {
+        let mut world = World::new(seed)
+            .with_safety(self.scenario.safety)
+            .with_network(self.scenario.network.clone());
+        world.platooning = self.scenario.platooning;
+        world
+    }
+    // Size the map texture and the camera to the network of the world
+    fn fit_view(&mut self) {
+        let map = self.world.network.map;
+        (self.view, self.view_scale) = map_texture(&self.canvas, self.texture_creator, map);
+        self.camera = camera::Camera::new(map, self.canvas.output_size().unwrap());
+    }
+    // Write every spawn (and every vehicle state when `states` is set) to a run log
+    pub fn with_recorder(mut self, path: &str, states: bool) -> std::io::Result<Self> {
+        self.recorder = Some(Recorder::create(path, self.world.seed, states)?);
+        Ok(self)
+    }
+    // Play back a run log instead of generating vehicles, on the network set before,
+    // an error when its vehicles enter intersections the network does not have
+    pub fn with_replay(mut self, replay: Replay) -> std::io::Result<Self> {
+        replay.check(&self.scenario.network)?;
+        self.world = self.new_world(replay.seed);
+        self.player = Some(Player { replay, cursor: 0 });
+        Ok(self)
+    }
+
+    // Write a PNG every CAPTURE_TICKS into `dir`, running as fast as possible until the run is over
+    pub fn with_capture(mut self, dir: &str) -> std::io::Result<Self> {
+        std::fs::create_dir_all(dir)?;
+        self.capture = Some(Capture {
+            dir: dir.into(),
+            frame: 0,
+            last_tick: None,
+            max: CAPTURE_MAX,
+        });
+        Ok(self)
+    }
+    // Stop the capture after `max` of simulated time, even if vehicles are left
+    pub fn with_capture_max(mut self, max: Duration) -> Self {
+        if let Some(capture) = self.capture.as_mut() {
+            capture.max = max;
         }
+        self
     }
 
     // Main simulation loop that handles events, updates the state, and renders the simulation
     pub fn run(&mut self) {
         let mut running = true;
+        if self.capture.is_some() && self.player.is_none() {
+            // nobody is there to press A, run one auto-spawn loop
+            self.spawn_loop = (self.world.clock(),self.scenario.length);
+            self.demand_clock = Duration::ZERO;
+        }
         while running {
             self.handle_events(&mut running);  // This will need to be async as well
         
             if !self.stat_showing {
-                if !self.paused{
+                if !self.paused || self.step > 0{
                     self.update();
+                    self.step = self.step.saturating_sub(1);
                 }
+                self.trajectories.record(&self.world);
+                let frame = self.last_frame.elapsed().as_secs_f64();
+                self.frame_time = if self.frame_time > 0.0 { self.frame_time * 0.9 + frame * 0.1 } else { frame };
+                self.last_frame = Instant::now();
                 self.render();
+                if self.capture.is_some() {
+                    // captured frames follow the simulated time, not the real one
+                    running = !self.capture_done();
+                    continue;
+                }
                 match self.speed_boost{
                     0 => {::std::thread::sleep(self.refresh_time)}      // normal refresh
                     1 => {::std::thread::sleep(self.refresh_time/2)}    // boosted refresh
@@ -133,62 +402,201 @@ impl Simulation {
                 self.render_stat();
             }
         }
+        if let Some(recorder) = self.recorder.as_mut() {
+            if let Err(e) = recorder.finish(self.world.tick) {
+                eprintln!("Failed to write run log: {}", e);
+            }
+        }
+    }
+    // Whether a captured run is over: the run log is played back, or the auto-spawn loop ended and every vehicle left,
+    // or the longest capture time is reached
+    fn capture_done(&self) -> bool {
+        if self.capture.as_ref().is_some_and(|c| self.world.clock() >= c.max) {
+            return true;
+        }
+        match self.player.as_ref() {
+            Some(player) => self.world.tick >= player.replay.length(),
+            None => {
+                self.world.clock() >= self.spawn_loop.0 + self.spawn_loop.1
+                    && self.world.vehicles.is_empty()
+                    && self.world.queues.is_empty()
+            }
+        }
     }
      // Update the state of vehicles and traffic lights
      fn update(&mut self) {
-        if self.spawn_loop.1.as_secs_f32()>0.0
-        && self.spawn_loop.0 + self.cooldown < Instant::now() 
-        {
-            let directions = [
-                Direction::North,
-                Direction::South,
-                Direction::East,
-                Direction::West,
-            ];
-            let random_direction = directions[self.rng.gen_range(0..4)];
-            // self.spawn_vehicle(random_direction);
-            let mut vehicle = Vehicle::new(random_direction);
-            let is_overlapping = vehicle.is_overlapping(self.sector.clone(),self.vehicles.clone());
-                if is_overlapping == 0 
-                && self.vehicles.len()< NUMBER_AV{
-                    self.vehicles.push_back(vehicle);
-                }
-            self.last_spawn_time = Instant::now(); // Update the last spawn time
-            self.spawn_loop.1 -= self.cooldown;
-            self.spawn_loop.0 = Instant::now();
-        }
-        for i in 0..self.vehicles.len() {
-            let vehicles = self.vehicles.clone();
-            // turn if vehicle need
-            let velocity =self.vehicles[i].turn(self.sector.clone());
-            // move forward if vehicle can
-            self.vehicles[i].forward(vehicles.clone(),self.sector.clone(),velocity);
-        }
-        // Retain only the vehicles that have not yet arrived
-        self.vehicles.retain(|vehicle| {
-            let arrived = 
-            !vehicle.body.intersection(self.sector.map).is_some()
-            && !vehicle.hitbox.urgency_stop.intersection(self.sector.map).is_some();
-            
-            if arrived {
-                let mut t_av = vehicle.clone();
-                t_av.arrival();
-                self.stats.push_back(t_av.stats);
+        if self.player.is_some() {
+            self.playback();
+            return;
+        }
+        // Keep a state every CHECKPOINT_TICKS for rewinding
+        let due = self.world.tick.is_multiple_of(CHECKPOINT_TICKS)
+            && self.history.back().is_none_or(|c| c.world.tick != self.world.tick);
+        if self.history.is_empty() || due {
+            if self.history.len() > REWIND_TICKS / CHECKPOINT_TICKS as usize {
+                self.history.pop_front();
             }
-            
-            !arrived
-        });
-        // println!("{}",self.vehicles.len());
+            self.history.push_back(Checkpoint { world: self.world.clone(), demand_clock: self.demand_clock, spawns: Vec::new() });
+        }
+        // Generate the demand of the auto-spawn loop since the last update,
+        // from a stream of the seed and the tick so a restored snapshot draws the same vehicles
+        let elapsed = self.world.clock().saturating_sub(self.spawn_loop.0).min(self.spawn_loop.1);
+        if elapsed > self.demand_clock {
+            self.demand.reseed(world::stream_seed(demand_seed(self.world.seed), self.world.tick as u64));
+            for spawn in self.demand.generate(self.demand_clock, elapsed) {
+                self.spawn_vehicle(spawn.route, spawn.direction);
+            }
+            self.demand_clock = elapsed;
+        }
+        self.world.step();
+        if let Some(recorder) = self.recorder.as_mut() {
+            if let Err(e) = recorder.frame(self.world.tick, &self.world.vehicles) {
+                eprintln!("Failed to write run log: {}", e);
+            }
+        }
     }
 
-    // Handle input events such as quitting, spawning vehicles in different directions, and random vehicle spawning
-    fn handle_events(&mut self, running: &mut bool) {
-        let events: Vec<Event> = self.event_pump.poll_iter().collect();
+    // Spawn a vehicle on the given approach, turning as the OD matrix says
+    fn spawn_on(&mut self, route: Direction) {
         let now = Instant::now();
         if now.duration_since(self.last_spawn_time) < self.cooldown {
             return; // If cooldown period has not passed, return early
         }
+        self.last_spawn_time = now; // Update the last spawn time
+        let direction = self.scenario.od.pick_turn(route, &mut self.rng);
+        self.spawn_vehicle(route, direction);
+    }
+
+    // Queue a vehicle on its lane, it enters the map once there is room for it
+    fn spawn_vehicle(&mut self, route: Direction, direction: Direction) {
+        if self.player.is_some() {
+            return;
+        }
+        // no lane for that turn on the arms of the intersections
+        let Some(event) = self.world.spawn(route, direction) else { return };
+        if let Some(checkpoint) = self.history.back_mut() {
+            checkpoint.spawns.push((event.tick, route, direction));
+        }
+        if let Some(recorder) = self.recorder.as_mut() {
+            if let Err(e) = recorder.spawn(&event) {
+                eprintln!("Failed to write run log: {}", e);
+            }
+        }
+    }
+
+    // Pause and run the given number of ticks
+    fn step_forward(&mut self, ticks: u32) {
+        self.paused = true;
+        self.step += ticks;
+    }
+    // Pause and go back the given number of ticks, as far as the history allows
+    fn rewind(&mut self, ticks: u32) {
+        self.paused = true;
+        self.step = 0;
+        if self.player.is_some() {
+            self.scrub(self.world.tick.saturating_sub(ticks));
+            return;
+        }
+        if self.recorder.is_some() {
+            eprintln!("Rewind is disabled while a run log is written");
+            return;
+        }
+        // from the last state kept before the target, or the oldest one
+        let target = self.world.tick.saturating_sub(ticks);
+        while self.history.len() > 1 && self.history.back().is_some_and(|c| c.world.tick > target) {
+            self.history.pop_back();
+        }
+        let Some(checkpoint) = self.history.back_mut() else { return };
+        let target = target.max(checkpoint.world.tick);
+        checkpoint.spawns.retain(|s| s.0 < target);
+        self.world = checkpoint.world.clone();
+        self.summary = None;
+        self.demand_clock = checkpoint.demand_clock;
+        let spawns = checkpoint.spawns.clone();
+        // simulate again up to the target, the spawns drawing the same vehicles from the same state
+        while self.world.tick < target {
+            let tick = self.world.tick;
+            for &(_, route, direction) in spawns.iter().filter(|s| s.0 == tick) {
+                self.world.spawn(route, direction);
+            }
+            let elapsed = self.world.clock().saturating_sub(self.spawn_loop.0).min(self.spawn_loop.1);
+            self.demand_clock = self.demand_clock.max(elapsed);
+            self.world.step();
+        }
+    }
+
+    // Capture the complete state of the simulation
+    #[cfg(feature = "serde")]
+    pub fn snapshot(&self) -> snapshot::Snapshot {
+        snapshot::Snapshot {
+            world: self.world.clone(),
+            spawn_loop: self.spawn_loop,
+            demand_clock: self.demand_clock,
+        }
+    }
+    // Resume the simulation from a captured state, under the controls of the intersections chosen for this run
+    #[cfg(feature = "serde")]
+    pub fn restore(&mut self, mut snapshot: snapshot::Snapshot) {
+        for (node, current) in snapshot.world.network.nodes.iter_mut().zip(&self.world.network.nodes) {
+            node.sector.control = current.sector.control;
+        }
+        self.world = snapshot.world;
+        self.fit_view();
+        self.summary = None;
+        self.history.clear();
+        self.trajectories = trajectory::Trajectories::new();
+        self.spawn_loop = snapshot.spawn_loop;
+        self.demand_clock = snapshot.demand_clock;
+    }
+
+    // Advance the played back run by one tick
+    fn playback(&mut self) {
+        let Some(player) = self.player.as_mut() else { return };
+        if self.world.tick >= player.replay.length() {
+            self.paused = true;
+            return;
+        }
+        if player.replay.frames.is_empty() {
+            // re-simulate from the recorded spawns
+            while let Some(event) = player.replay.spawns.get(player.cursor) {
+                if event.tick > self.world.tick {
+                    break;
+                }
+                self.world.push(event);
+                player.cursor += 1;
+            }
+            self.world.step();
+        } else {
+            // show the recorded states
+            let frame = player.replay.frames.partition_point(|f| f.tick <= self.world.tick);
+            if let Some(frame) = player.replay.frames.get(frame) {
+                self.world.vehicles = frame.vehicles.iter().map(Vehicle::from_state).collect();
+                self.world.tick = frame.tick;
+            }
+        }
+    }
+    // Move the played back run to the given tick
+    fn scrub(&mut self, tick: u32) {
+        let Some(player) = self.player.as_ref() else { return };
+        let (tick, seed) = (tick.min(player.replay.length()), player.replay.seed);
+        if player.replay.frames.is_empty() {
+            if tick < self.world.tick {
+                self.world = self.new_world(seed);
+                self.player.as_mut().unwrap().cursor = 0;
+                self.summary = None;
+            }
+            while self.world.tick < tick {
+                self.playback();
+            }
+        } else {
+            self.world.tick = tick.saturating_sub(1);
+            self.playback();
+        }
+    }
 
+    // Handle input events such as quitting, spawning vehicles in different directions, and random vehicle spawning
+    fn handle_events(&mut self, running: &mut bool) {
+        let events: Vec<Event> = self.event_pump.poll_iter().collect();
         for event in events {
             match event {
                 Event::Quit { 
@@ -207,82 +615,146 @@ impl Simulation {
                     }
                 }
                 Event::KeyDown {
-                    keycode: Some(Keycode::Up),
+                    keycode: Some(Keycode::Left),
+                    ..
+                } if self.player.is_some() => {
+                    self.scrub(self.world.tick.saturating_sub(SCRUB_TICKS));
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::Right),
+                    ..
+                } if self.player.is_some() => {
+                    self.scrub(self.world.tick + SCRUB_TICKS);
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::Home),
+                    ..
+                } if self.player.is_some() => {
+                    self.scrub(0);
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::N),
                     ..
                 } => {
-                    // self.spawn_vehicle(Direction::North);
-                    let mut vehicle = Vehicle::new(Direction::North);
-                    let is_overlapping = vehicle.is_overlapping(self.sector.clone(),self.vehicles.clone());
-                    if is_overlapping == 0 
-                    && self.vehicles.len()< NUMBER_AV{
-                        self.vehicles.push_back(vehicle);
+                    self.step_forward(1);
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::M),
+                    ..
+                } => {
+                    self.step_forward(STEP_TICKS);
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::B),
+                    ..
+                } => {
+                    self.rewind(1);
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::V),
+                    ..
+                } => {
+                    self.rewind(STEP_TICKS);
+                }
+                #[cfg(feature = "serde")]
+                Event::KeyUp {
+                    keycode: Some(Keycode::F5),
+                    ..
+                } => {
+                    if self.player.is_some() {
+                        eprintln!("Snapshots are disabled during playback");
+                    } else if let Err(e) = self.snapshot().save(SNAPSHOT_PATH) {
+                        eprintln!("Failed to save snapshot: {}", e);
+                    }
+                }
+                #[cfg(feature = "serde")]
+                Event::KeyUp {
+                    keycode: Some(Keycode::F9),
+                    ..
+                } => {
+                    // the tick would go back in the run log
+                    if self.player.is_some() || self.recorder.is_some() {
+                        eprintln!("Restoring a snapshot is disabled while a run log is written or played back");
+                        continue;
+                    }
+                    match snapshot::Snapshot::load(SNAPSHOT_PATH) {
+                        Ok(snapshot) => self.restore(snapshot),
+                        Err(e) => eprintln!("Failed to load snapshot: {}", e),
                     }
-                    self.last_spawn_time = now; // Update the last spawn time
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::Up),
+                    ..
+                } => {
+                    self.spawn_on(Direction::North);
                 }
                 Event::KeyDown {
                     keycode: Some(Keycode::Down),
                     ..
                 } => {
-                    // self.spawn_vehicle(Direction::South);
-                    let mut vehicle = Vehicle::new(Direction::South);
-                    let is_overlapping = vehicle.is_overlapping(self.sector.clone(),self.vehicles.clone());
-                    if  is_overlapping == 0 
-                    && self.vehicles.len()< NUMBER_AV{
-                        self.vehicles.push_back(vehicle);
-                    }
-                    self.last_spawn_time = now; // Update the last spawn time
+                    self.spawn_on(Direction::South);
                 }
                 Event::KeyDown {
                     keycode: Some(Keycode::Right),
                     ..
                 } => {
-                    // self.spawn_vehicle(Direction::East);
-                    let mut vehicle = Vehicle::new(Direction::East);
-                    let is_overlapping = vehicle.is_overlapping(self.sector.clone(),self.vehicles.clone());
-                    if is_overlapping == 0 
-                    && self.vehicles.len()< NUMBER_AV{
-                        self.vehicles.push_back(vehicle);
-                    }
-                    self.last_spawn_time = now; // Update the last spawn time
+                    self.spawn_on(Direction::East);
                 }
                 Event::KeyDown {
                     keycode: Some(Keycode::Left),
                     ..
                 } => {
-                    // self.spawn_vehicle(Direction::West);
-                    let mut vehicle = Vehicle::new(Direction::West);
-                    let is_overlapping = vehicle.is_overlapping(self.sector.clone(),self.vehicles.clone());
-                    if is_overlapping == 0 
-                    && self.vehicles.len()< NUMBER_AV{
-                        self.vehicles.push_back(vehicle);
-                    }
-                    self.last_spawn_time = now; // Update the last spawn time
+                    self.spawn_on(Direction::West);
                 }
                 Event::KeyDown {
                     keycode: Some(Keycode::R),
                     ..
                 } => {
-                    let directions = [
-                        Direction::North,
-                        Direction::South,
-                        Direction::East,
-                        Direction::West,
-                    ];
-                    let random_direction = directions[self.rng.gen_range(0..4)];
-                    // self.spawn_vehicle(random_direction);
-                    let mut vehicle = Vehicle::new(random_direction);
-                    let is_overlapping = vehicle.is_overlapping(self.sector.clone(),self.vehicles.clone());
-                    if is_overlapping == 0 
-                    && self.vehicles.len()< NUMBER_AV{
-                        self.vehicles.push_back(vehicle);
-                    }
-                    self.last_spawn_time = now; // Update the last spawn time
+                    let random_direction = APPROACHES[self.rng.gen_range(0..APPROACHES.len())];
+                    self.spawn_on(random_direction);
+                }
+                Event::MouseButtonDown {
+                    mouse_btn: MouseButton::Left,
+                    x,
+                    y,
+                    ..
+                } => {
+                    let point = self.camera.to_map(Point::new(x, y));
+                    self.selected = self.world.vehicles
+                        .iter()
+                        .find(|vehicle| vehicle.body.contains_point(point))
+                        .map(|vehicle| vehicle.id());
+                }
+                Event::MouseWheel { y, .. } => {
+                    let mouse = self.event_pump.mouse_state();
+                    self.camera.zoom_at(Point::new(mouse.x(), mouse.y()), ZOOM_STEP.powi(y));
+                }
+                Event::MouseMotion {
+                    mousestate,
+                    xrel,
+                    yrel,
+                    ..
+                } if mousestate.right() => {
+                    self.camera.pan(xrel, yrel);
+                }
+                Event::KeyUp {
+                    keycode: Some(Keycode::Z),
+                    ..
+                } => {
+                    self.camera.reset();
+                }
+                Event::Window {
+                    win_event: WindowEvent::SizeChanged(width, height),
+                    ..
+                } => {
+                    self.camera.resize(width.max(1) as u32, height.max(1) as u32);
                 }
                 Event::KeyUp {
                     keycode: Some(Keycode::A),
                     ..
                 } => {
-                    self.spawn_loop = (Instant::now(),Duration::from_secs_f32(60.0));
+                    self.spawn_loop = (self.world.clock(),self.scenario.length);
+                    self.demand_clock = Duration::ZERO;
                 }
                 Event::KeyDown {
                     keycode: Some(Keycode::Space),
@@ -302,12 +774,61 @@ impl Simulation {
                 } => {
                     self.visibility.1 = !self.visibility.1;
                 }
+                Event::KeyUp {
+                    keycode: Some(Keycode::Tab),
+                    ..
+                } if self.stat_showing => {
+                    self.stat_page = (self.stat_page + 1) % STAT_PAGES;
+                }
+                Event::KeyUp {
+                    keycode: Some(Keycode::E),
+                    ..
+                } if self.stat_showing => {
+                    let summary = self.summary(0).clone();
+                    let written = summary
+                        .write_csv(STATS_PATH)
+                        .and_then(|_| summary.write_totals_csv(TOTALS_PATH))
+                        .and_then(|_| summary.write_los_csv(LOS_PATH))
+                        .and_then(|_| self.world.safety.write_csv(SAFETY_PATH))
+                        .and_then(|_| sumo::write_fcd(&self.trajectories, self.world.network.map, FCD_PATH));
+                    match written {
+                        Ok(()) => println!("Statistics written to {}, {}, {}, {} and {}", STATS_PATH, TOTALS_PATH, LOS_PATH, SAFETY_PATH, FCD_PATH),
+                        Err(e) => eprintln!("Failed to write statistics: {}", e),
+                    }
+                }
+                Event::KeyUp {
+                    keycode: Some(Keycode::H),
+                    ..
+                } => {
+                    self.hud = !self.hud;
+                }
                 Event::KeyUp {
                     keycode: Some(Keycode::O),
                     ..
                 } => {
                     self.visibility.0 = !self.visibility.0;
                 }
+                Event::KeyUp {
+                    keycode: Some(Keycode::L),
+                    ..
+                } => {
+                    self.trails = !self.trails;
+                }
+                Event::KeyUp {
+                    keycode: Some(Keycode::C),
+                    ..
+                } => {
+                    self.heatmap = !self.heatmap;
+                }
+                Event::KeyUp {
+                    keycode: Some(Keycode::X),
+                    ..
+                } => {
+                    match self.export_heatmap() {
+                        Ok(()) => println!("Heatmap written to {}", HEATMAP_PATH),
+                        Err(e) => eprintln!("Failed to write heatmap: {}", e),
+                    }
+                }
                 Event::KeyUp {
                     keycode: Some(Keycode::T),
                     ..
@@ -331,120 +852,261 @@ impl Simulation {
 
     // Render the simulation, including roads, and vehicles
     fn render(&mut self) {
-        // Draw the road
-        draw_road(&mut self.canvas);
-        if self.visibility.1{
-            // Render zone hitbox
-            self.canvas.set_draw_color(Color::RGBA(75, 75, 0,85));
-            self.canvas.fill_rect(self.sector.entry_intersect).unwrap();
-            self.canvas.set_draw_color(Color::RGBA(125, 42, 42,85));
-            self.canvas.fill_rect(self.sector.in_intersect).unwrap();
-            // Left turn point
-            self.canvas.set_draw_color(Color::RGB(110, 0, 0));
-            self.canvas.fill_rect(self.sector.turn_north.0).unwrap();
-            self.canvas.fill_rect(self.sector.turn_north.1).unwrap();
-            self.canvas.set_draw_color(Color::RGB(0, 110, 0));
-            self.canvas.fill_rect(self.sector.turn_east.1).unwrap();
-            self.canvas.fill_rect(self.sector.turn_east.0).unwrap();
-            self.canvas.set_draw_color(Color::RGB(0, 0, 110));
-            self.canvas.fill_rect(self.sector.turn_south.0).unwrap();
-            self.canvas.fill_rect(self.sector.turn_south.1).unwrap();
-            self.canvas.set_draw_color(Color::RGB(110, 110, 0));
-            self.canvas.fill_rect(self.sector.turn_west.0).unwrap();
-            self.canvas.fill_rect(self.sector.turn_west.1).unwrap();
-            // Right turn point
-        }
-        // Set the position and size of the image on the screen
-        let width = VEHICLE_WIDTH;      // Width of the image
-        let height = VEHICLE_HEIGHT;     // Height of the image
-
-        // Render vehicles
-        for vehicle in &self.vehicles {
-            if self.visibility.0{
-                // Render the body and hitbox
-                self.canvas.set_draw_color(Color::RGBA(225, 225, 90,125));
-                self.canvas.fill_rect(vehicle.hitbox.slowdown_2).unwrap();
-                self.canvas.set_draw_color(Color::RGBA(90, 90, 255,125));
-                self.canvas.fill_rect(vehicle.hitbox.left).unwrap();
-                self.canvas.fill_rect(vehicle.hitbox.right).unwrap();
-                self.canvas.set_draw_color(Color::RGBA(125, 125, 0,125));
-                self.canvas.fill_rect(vehicle.hitbox.slowdown_1).unwrap();
-                self.canvas.set_draw_color(Color::RGBA(125, 0, 0,125));
-                self.canvas.fill_rect(vehicle.hitbox.closer).unwrap();
-                self.canvas.fill_rect(vehicle.hitbox.urgency_stop).unwrap();
-                self.canvas.set_draw_color(Color::RGBA(0, 125, 0,125));
-                self.canvas.fill_rect(vehicle.body).unwrap();
+        // Draw the map at full size, then show the part seen by the camera
+        let Simulation { canvas, view, view_scale, world, atlas, trajectories, visibility, selected, heatmap, trails, .. } = self;
+        canvas.with_texture_canvas(view, |canvas| {
+            canvas.set_scale(*view_scale, *view_scale).unwrap();
+            // Draw the roads of every intersection
+            draw_network(canvas, &world.network);
+            if visibility.1{
+                for sector in world.network.nodes.iter().map(|n| &n.sector) {
+                    // Render zone hitbox
+                    canvas.set_draw_color(Color::RGBA(75, 75, 0,85));
+                    canvas.fill_rect(sector.entry_intersect).unwrap();
+                    canvas.set_draw_color(Color::RGBA(125, 42, 42,85));
+                    canvas.fill_rect(sector.in_intersect).unwrap();
+                    // Turn points, one colour per approach
+                    for turn in &sector.turns {
+                        canvas.set_draw_color(match turn.route {
+                            Direction::North => Color::RGB(110, 0, 0),
+                            Direction::East => Color::RGB(0, 110, 0),
+                            Direction::South => Color::RGB(0, 0, 110),
+                            _ => Color::RGB(110, 110, 0),
+                        });
+                        canvas.fill_rect(turn.area).unwrap();
+                    }
+                }
+            }
+            if *heatmap {
+                world.heatmap.draw(canvas);
             }
+            // Set the position and size of the image on the screen
+            let width = VEHICLE_WIDTH;      // Width of the image
+            let height = VEHICLE_HEIGHT;     // Height of the image
 
-            let rotation_angle = match vehicle.route {
-                Direction::North => 180.0,
-                Direction::East => 270.0,
-                Direction::South => 0.0,
-                Direction::West => 90.0,
-                _ => todo!(),
-            };
-            let position_x = vehicle.position.x as i32 - (VEHICLE_WIDTH as i32)/2;
-            let position_y = vehicle.position.y as i32 - (VEHICLE_HEIGHT as i32)/2;
-            let destination_rect = Rect::new(position_x, position_y, width, height);
+            // Render vehicles
+            for vehicle in &world.vehicles {
+                if visibility.0 || *selected == Some(vehicle.id()){
+                    // Render the body and hitbox
+                    overlay::draw_hitboxes(canvas, vehicle);
+                }
 
-            let texture_creator = self.canvas.texture_creator();
-    
-            match texture_creator.load_texture(&vehicle.texture) {
-                Ok(texture) => {
-                    self.canvas.copy_ex(
-                        &texture,
-                        None,
-                        Some(destination_rect),
-                        rotation_angle,
-                        None,
-                        false,
-                        false,
-                    )
-                    .unwrap();
-                }
-                Err(e) => eprintln!("Failed to load texture: {}", e),
-            };
+                if *trails {
+                    let mut trail = trajectories.trail(vehicle.id());
+                    trail.push(vehicle.position);
+                    overlay::draw_trail(canvas, &trail);
+                }
+
+                let rotation_angle = match vehicle.route {
+                    Direction::North => 180.0,
+                    Direction::East => 270.0,
+                    Direction::South => 0.0,
+                    Direction::West => 90.0,
+                    _ => todo!(),
+                };
+                let position_x = vehicle.position.x as i32 - (VEHICLE_WIDTH as i32)/2;
+                let position_y = vehicle.position.y as i32 - (VEHICLE_HEIGHT as i32)/2;
+                let destination_rect = Rect::new(position_x, position_y, width, height);
+
+                let (texture, sprite) = atlas.sprite(vehicle.class);
+                canvas.copy_ex(
+                    texture,
+                    Some(sprite),
+                    Some(destination_rect),
+                    rotation_angle,
+                    None,
+                    false,
+                    false,
+                )
+                .unwrap();
+            }
+            // Outline of the inspected vehicle
+            if let Some(vehicle) = selected.and_then(|id| world.vehicles.iter().find(|v| v.id() == id)) {
+                canvas.set_draw_color(Color::RGB(255, 255, 255));
+                canvas.draw_rect(vehicle.body).unwrap();
+            }
+        }).unwrap();
+        self.canvas.set_draw_color(Color::RGB(0, 0, 0));
+        self.canvas.clear();
+        // only the part of the view over the map, the rest of the window stays black
+        if let Some((part, window)) = self.camera.visible() {
+            let scale = |v: i32| (v as f32 * self.view_scale).round() as i32;
+            let part = Rect::new(
+                scale(part.x()),
+                scale(part.y()),
+                (scale(part.right()) - scale(part.x())).max(1) as u32,
+                (scale(part.bottom()) - scale(part.y())).max(1) as u32,
+            );
+            self.canvas.copy(&self.view, Some(part), Some(window)).unwrap();
+        }
+
+        let (screen_width, _) = self.canvas.output_size().unwrap();
+        if self.hud {
+            let lines = self.hud_lines();
+            let (width, _) = overlay::panel_size(&self.font, &lines);
+            overlay::draw_panel(&mut self.canvas, &self.font, &lines, screen_width as i32 - width as i32 - 10, 10);
+        }
+        // Inspect panel of the selected vehicle, dropped once it has left the map
+        match self.selected.and_then(|id| self.world.vehicles.iter().find(|v| v.id() == id)) {
+            Some(vehicle) => {
+                let lines = overlay::vehicle_lines(vehicle);
+                overlay::draw_panel(&mut self.canvas, &self.font, &lines, 10, 10);
+            }
+            None => self.selected = None,
+        }
+        if let Some(capture) = self.capture.as_mut() {
+            if let Err(e) = capture.frame(&self.canvas, self.world.tick) {
+                eprintln!("Failed to capture frame: {}", e);
+            }
         }
         self.canvas.present(); // Present the updated canvas to the screen
     }
+    // Write the heatmap drawn over the whole road to a PNG
+    fn export_heatmap(&mut self) -> Result<(), String> {
+        let (heatmap, network, scale) = (&self.world.heatmap, &self.world.network, self.view_scale);
+        let mut saved = Ok(());
+        self.canvas
+            .with_texture_canvas(&mut self.view, |canvas| {
+                canvas.set_scale(scale, scale).unwrap();
+                draw_network(canvas, network);
+                heatmap.draw(canvas);
+                saved = overlay::save_png(canvas, HEATMAP_PATH);
+            })
+            .map_err(|e| e.to_string())?;
+        saved
+    }
+    // Statistics of the world summarised at most `age` ticks ago, sorting every distribution is too slow for each frame
+    fn summary(&mut self, age: u32) -> &StatsSummary {
+        let tick = self.world.tick;
+        if self.summary.as_ref().is_none_or(|(at, _)| *at > tick || tick - at > age) {
+            self.summary = Some((tick, StatsSummary::new(&self.world)));
+        }
+        &self.summary.as_ref().unwrap().1
+    }
+    // Running metrics shown by the heads-up display, the counts of the moment and the statistics of the last second
+    fn hud_lines(&mut self) -> Vec<String> {
+        let summary = self.summary(SUMMARY_TICKS).clone();
+        let vehicles = &self.world.vehicles;
+        let remaining = (self.spawn_loop.0 + self.spawn_loop.1).saturating_sub(self.world.clock());
+        let mut lines = vec![
+            format!("Time: {:.1}", self.world.clock().as_secs_f64()),
+            format!("Active: {}", vehicles.len()),
+            format!("Queued: {}", self.world.queues.len()),
+            format!("Arrived: {}", self.world.stats.len()),
+            format!("Throughput: {:.1} /min", summary.throughput),
+            format!("Mean Time: {:.2}", summary.time.mean),
+            format!("Colision: {} ({} total)", vehicles.iter().filter(|v| v.in_collision()).count(), summary.colision + summary.in_progress_colision),
+            format!("Close Call: {} ({} total)", vehicles.iter().filter(|v| v.in_close_call()).count(), summary.close_call + summary.in_progress_close_call),
+            format!("Speed: x{:.2}{}", self.time_multiplier(), if self.paused { " paused" } else { "" }),
+            format!("Auto Spawn: {:.1}", remaining.as_secs_f64()),
+        ];
+        if self.heatmap {
+            let total = self.world.heatmap.total();
+            lines.push(format!("Heatmap: {} close calls, {} colisions, {} hard stops", total.close_call, total.colision, total.hard_stop));
+        }
+        lines
+    }
+    // Simulated time per real time, measured over the last frames
+    fn time_multiplier(&self) -> f64 {
+        if self.paused || self.frame_time <= 0.0 {
+            return 0.0;
+        }
+        TICK.as_secs_f64() / self.frame_time
+    }
     // The render_stat function
     fn render_stat(&mut self) {
-        // Initialize TTF context if not done already
-        let ttf_context = sdl2::ttf::init().expect("Failed to initialize TTF context");
-
-        let font_path = "font/RubikGlitch-Regular.ttf";
-        let font = ttf_context.load_font(font_path, 45).expect("Failed to load font");
-
+        // the pages are laid out for the initial window size, scaled to the actual one
+        self.canvas.set_logical_size(SCREEN_WIDTH, SCREEN_HEIGHT).unwrap();
+        match self.stat_page {
+            1 => self.render_charts(),
+            2 => self.render_tables(),
+            3 => self.render_time_space(),
+            _ => self.render_summary(),
+        }
+        overlay::draw_text(&mut self.canvas, &self.font, "Tab: next page   Escape: quit", 10, SCREEN_HEIGHT as i32 - 30, Color::RGB(225, 225, 255));
+        // Present the updated canvas to the screen
+        self.canvas.present();
+        self.canvas.set_logical_size(0, 0).unwrap();
+    }
+    // Time series, travel time histogram and delay per approach
+    fn render_charts(&mut self) {
+        self.canvas.set_draw_color(Color::RGB(25, 25, 25));
+        self.canvas.clear();
+        let (half_width, half_height) = (SCREEN_WIDTH / 2, (SCREEN_HEIGHT - 40) / 2);
+        let area = |column: u32, row: u32| {
+            Rect::new((half_width * column) as i32 + 10, (half_height * row) as i32 + 10, half_width - 20, half_height - 20)
+        };
+        let stats = &self.world.stats;
+        let series = &self.world.series;
+        chart::draw_line_chart(&mut self.canvas, &self.font, area(0, 0), "Throughput (veh/min) over time (s)", &report::throughput_series(series), Color::RGB(90, 200, 90));
+        chart::draw_line_chart(&mut self.canvas, &self.font, area(1, 0), "Vehicles in intersection over time (s)", &report::intersection_series(series), Color::RGB(200, 90, 90));
+        chart::draw_bar_chart(&mut self.canvas, &self.font, area(0, 1), "Travel time (s) histogram", &report::travel_time_histogram(stats, 10), Color::RGB(90, 90, 220));
+        chart::draw_bar_chart(&mut self.canvas, &self.font, area(1, 1), "Mean control delay (s) per approach", &report::approach_delays(stats), Color::RGB(220, 180, 60));
+    }
+    // Time-space diagram of every lane, one column per approach and one row per turn
+    fn render_time_space(&mut self) {
+        self.canvas.set_draw_color(Color::RGB(25, 25, 25));
+        self.canvas.clear();
+        let (width, height) = (SCREEN_WIDTH / APPROACHES.len() as u32, (SCREEN_HEIGHT - 40) / TURNS.len() as u32);
+        for (column, &approach) in APPROACHES.iter().enumerate() {
+            for (row, &lane) in TURNS.iter().enumerate() {
+                let area = Rect::new((width * column as u32) as i32 + 5, (height * row as u32) as i32 + 10, width - 10, height - 20);
+                let title = format!("{:?} {:?}: px over s", approach, lane);
+                let lines = self.trajectories.time_space(approach, lane);
+                chart::draw_lines_chart(&mut self.canvas, &self.font, area, &title, &lines, Color::RGB(90, 200, 200));
+            }
+        }
+    }
+    // Arrivals broken down per approach and per turn
+    fn render_tables(&mut self) {
+        self.canvas.set_draw_color(Color::RGB(25, 25, 25));
+        self.canvas.clear();
+        let stats = &self.world.stats;
+        chart::draw_table(&mut self.canvas, &self.font, 40, 40, 150, &report::approach_table(stats));
+        chart::draw_table(&mut self.canvas, &self.font, 40, 300, 150, &report::turn_table(stats));
+        let los = self.summary(0).los_table();
+        chart::draw_table(&mut self.canvas, &self.font, 40, 500, 150, &los);
+    }
+    // Totals of the run
+    fn render_summary(&mut self) {
         // Set the background color
         self.canvas.set_draw_color(Color::RGB(25, 25, 25));
         self.canvas.clear();
 
-        // Calculate the stats
-        let total_av = self.stats.len();
-        let max_velocity = self.stats.iter().map(|s| s.velocity).max_by(|a, b| a.partial_cmp(b).unwrap()).unwrap();
-        let min_velocity = self.stats.iter().map(|s| s.velocity).min_by(|a, b| a.partial_cmp(b).unwrap()).unwrap();
-        let max_time = self.stats.iter().map(|s| s.time).max_by(|a, b| a.partial_cmp(b).unwrap()).unwrap();
-        let min_time = self.stats.iter().map(|s| s.time).min_by(|a, b| a.partial_cmp(b).unwrap()).unwrap();
-        let close_call:u32 = self.stats.iter().map(|s| s.close_call).sum();
-        let colision:u32 = self.stats.iter().map(|s| s.colision).sum();
-
-
-        // Define the text for display
-        let text_data = [
-            format!("Total Entries: {}", total_av),
-            format!("Max Velocity: {:.2}", max_velocity),
-            format!("Min Velocity: {:.2}", min_velocity),
-            format!("Max Time: {:.2}", max_time.as_secs_f64()),
-            format!("Min Time: {:.2}", min_time.as_secs_f64()),
-            format!("Colision: {}", colision),
-            format!("Close Call: {}", close_call)
-        ];
+        // Define the text for display: the lines too wide for the title font use the small one,
+        // split between their measures when still too wide for the window
+        let (screen_width, screen_height) = self.canvas.output_size().unwrap();
+        let fits = |font: &Font, text: &str| font.size_of(text).is_ok_and(|(w, _)| w + 40 <= screen_width);
+        let mut rows: Vec<(String, bool)> = Vec::new();
+        for line in self.summary(0).lines() {
+            if fits(&self.title_font, &line) {
+                rows.push((line, true));
+                continue;
+            }
+            let mut row = String::new();
+            for part in line.split("  ") {
+                let joined = if row.is_empty() { part.to_string() } else { format!("{}  {}", row, part) };
+                if !row.is_empty() && !fits(&self.font, &joined) {
+                    rows.push((row, false));
+                    row = format!("    {}", part);
+                } else {
+                    row = joined;
+                }
+            }
+            rows.push((row, false));
+        }
 
         // Set the text color
         let text_color = Color::RGB(225, 225, 255);
 
-        // Create a surface for each text and render it to the canvas
-        for (i, text) in text_data.iter().enumerate() {
+        // Create a surface for each text and render it to the canvas, one under the other
+        let heights: Vec<u32> = rows
+            .iter()
+            .map(|(_, title)| if *title { self.title_font.height() } else { self.font.height() } as u32)
+            .collect();
+        let mut y = (screen_height as i32 - heights.iter().sum::<u32>() as i32).max(0) / 2;
+        for ((text, title), row_height) in rows.iter().zip(heights) {
+            let font = if *title { &self.title_font } else { &self.font };
             let surface = font
                 .render(text)
                 .blended(text_color)
@@ -458,21 +1120,201 @@ impl Simulation {
             let TextureQuery { width, height, .. } = texture.query();
 
             // Set the position for the text rendering
-            let target = Rect::from_center(
-                Point::new(
-                    SCREEN_WIDTH as i32/2,
-                    (SCREEN_HEIGHT-(text_data.len()as u32*height))as i32/2 + (height*i as u32)as i32
-                    ),
-                  width, 
-                  height
-                );
+            let target = Rect::new((screen_width as i32 - width as i32) / 2, y, width, height);
+            y += row_height as i32;
 
             // Copy the texture to the canvas
             self.canvas.copy(&texture, None, Some(target)).expect("Render failed");
         }
+    }
+}
+// Seeds of the demand and of the keyboard turns, streams of their own derived from the seed of the world
+fn demand_seed(seed: u64) -> u64 {
+    seed.wrapping_add(1)
+}
+fn keys_seed(seed: u64) -> u64 {
+    seed.wrapping_add(2)
+}
 
-        // Present the updated canvas to the screen
-        self.canvas.present();
+// Target texture of the whole map, scaled down to the largest texture the renderer supports, and its scale
+fn map_texture(
+    canvas: &Canvas<Window>,
+    texture_creator: &'static TextureCreator<WindowContext>,
+    map: Rect,
+) -> (Texture<'static>, f32) {
+    let info = canvas.info();
+    // 0 when the renderer has no limit
+    let max = match info.max_texture_width.min(info.max_texture_height) {
+        0 => u32::MAX,
+        max => max,
+    };
+    let scale = (max as f32 / map.width().max(map.height()) as f32).min(1.0);
+    let (width, height) = ((map.width() as f32 * scale) as u32, (map.height() as f32 * scale) as u32);
+    let view = texture_creator
+        .create_texture_target(PixelFormatEnum::RGBA8888, width.max(1), height.max(1))
+        .expect("Failed to create map texture");
+    (view, scale)
+}
+
+// Background, then the crossing roads of each intersection on its tile
+fn draw_network(canvas: &mut Canvas<Window>, network: &network::Network) {
+    canvas.set_draw_color(Color::RGB(86, 125, 70));
+    canvas.clear();
+    for node in 0..network.nodes.len() {
+        // the viewport moves the origin of the drawing to the tile
+        let tile = network.tile(node);
+        canvas.set_viewport(tile);
+        draw_road(canvas);
+        let sector = &network.nodes[node].sector;
+        draw_layout(canvas, &sector.layout, Point::new(sector.center.x - tile.x(), sector.center.y - tile.y()));
+        if let Some(ring) = &sector.roundabout {
+            draw_roundabout(canvas, ring, Point::new(tile.x(), tile.y()));
+        } else {
+            draw_signs(canvas, sector, Point::new(tile.x(), tile.y()));
+        }
+    }
+    canvas.set_viewport(None);
+}
+// Grass over the arms and lanes missing from a layout, with a kerb along the road left, around `center`
+// (the lanes toward the intersection are on the left half of the north arm, the right half when driving on the left)
+fn draw_layout(canvas: &mut Canvas<Window>, layout: &layout::Layout, center: Point) {
+    let half_road = ROAD_WIDTH as i32 / 2;
+    let lane = (ROAD_WIDTH / (ROAD_NUMBER * 2)) as i32;
+    let far = SCREEN_WIDTH.max(SCREEN_HEIGHT) as i32;
+    for route in APPROACHES {
+        // drawn for the north arm, from the tile edge to the box, then turned to the arm
+        let (grass, kerbs) = match layout.arm(route) {
+            None => (vec![((-half_road - 2, -far), (half_road + 2, -half_road))], vec![((-half_road, -half_road - 2), (half_road, -half_road))]),
+            Some(arm) => {
+                let inner = -half_road + (ROAD_NUMBER as i32 - arm.lanes.len() as i32) * lane;
+                let outer = half_road - (ROAD_NUMBER as i32 - arm.exits as i32) * lane;
+                let mut grass = Vec::new();
+                let mut kerbs = Vec::new();
+                if inner > -half_road {
+                    grass.push(((-half_road - 2, -far), (inner, -half_road)));
+                    kerbs.push(((inner, -far), (inner + 2, -half_road)));
+                }
+                if outer < half_road {
+                    grass.push(((outer, -far), (half_road + 2, -half_road)));
+                    kerbs.push(((outer - 2, -far), (outer, -half_road)));
+                }
+                (grass, kerbs)
+            }
+        };
+        for (colour, rects) in [(Color::RGB(86, 125, 70), grass), (Color::RGB(175, 175, 175), kerbs)] {
+            canvas.set_draw_color(colour);
+            let mirror = |(x, y): (i32, i32)| (x * layout.side.sign(), y);
+            for (a, b) in rects {
+                canvas.fill_rect(layout::rect_to_map(center, mirror(a), mirror(b), route)).unwrap();
+            }
+        }
+    }
+}
+// Signs of the control of an intersection on the kerb side of its arms, and the stop lines at the edge of its box,
+// drawn in the tile of the intersection (`origin` being its top left corner)
+fn draw_signs(canvas: &mut Canvas<Window>, sector: &Sector, origin: Point) {
+    let center = Point::new(sector.center.x - origin.x, sector.center.y - origin.y);
+    let half_road = ROAD_WIDTH as i32 / 2;
+    let white = Color::RGB(235, 235, 235);
+    for route in APPROACHES {
+        let (Some(sign), Some(_)) = (sector.control.sign(route), sector.layout.arm(route)) else { continue };
+        // distance from the centre to the edge of the box on the side of the arm
+        let area = sector.in_intersect;
+        let edge = match route {
+            Direction::North => sector.center.y - area.top(),
+            Direction::South => area.bottom() - sector.center.y,
+            Direction::East => area.right() - sector.center.x,
+            _ => sector.center.x - area.left(),
+        };
+        // drawn for the north arm, then mirrored and turned to the arm
+        let local = |(x, y): (i32, i32)| (x * sector.layout.side.sign(), y);
+        let at = layout::to_map(center, local((-half_road - 16, -edge - 16)), route);
+        match sign {
+            control::Sign::Stop => {
+                canvas.set_draw_color(white);
+                canvas.fill_rect(layout::rect_to_map(center, local((-half_road, -edge - 5)), local((0, -edge - 1)), route)).unwrap();
+                // red octagon with a white bar
+                canvas.set_draw_color(Color::RGB(190, 30, 30));
+                for dy in -10i32..=10 {
+                    let dx = 10 - dy.abs().saturating_sub(6);
+                    canvas.fill_rect(Rect::new(at.x - dx, at.y + dy, (dx * 2 + 1) as u32, 1)).unwrap();
+                }
+                canvas.set_draw_color(white);
+                canvas.fill_rect(Rect::new(at.x - 6, at.y - 1, 13, 3)).unwrap();
+            }
+            control::Sign::PriorityRoad => {
+                // yellow diamond with a white border
+                for (r, colour) in [(11i32, white), (7, Color::RGB(240, 190, 20))] {
+                    canvas.set_draw_color(colour);
+                    for dy in -r..=r {
+                        let dx = r - dy.abs();
+                        canvas.fill_rect(Rect::new(at.x - dx, at.y + dy, (dx * 2 + 1) as u32, 1)).unwrap();
+                    }
+                }
+            }
+            control::Sign::PriorityToRight => {
+                // triangle pointing up, red border around a white inside with a black cross
+                for (inset, colour) in [(0, Color::RGB(190, 30, 30)), (4, white)] {
+                    canvas.set_draw_color(colour);
+                    for row in inset..=(20 - inset / 2) {
+                        let dx = row / 2 - inset / 2;
+                        canvas.fill_rect(Rect::new(at.x - dx, at.y - 10 + row, (dx * 2 + 1) as u32, 1)).unwrap();
+                    }
+                }
+                canvas.set_draw_color(Color::RGB(20, 20, 20));
+                for d in -3..=3 {
+                    canvas.fill_rect(Rect::new(at.x + d, at.y + 3 + d, 2, 2)).unwrap();
+                    canvas.fill_rect(Rect::new(at.x + d, at.y + 3 - d, 2, 2)).unwrap();
+                }
+            }
+        }
+    }
+}
+// Ring over the crossing, drawn in the tile of the intersection (`origin` being its top left corner)
+fn draw_roundabout(canvas: &mut Canvas<Window>, ring: &roundabout::Roundabout, origin: Point) {
+    let area = ring.ring();
+    let center = Point::new(ring.center.x - origin.x, ring.center.y - origin.y);
+    canvas.set_draw_color(Color::RGB(45, 45, 45));
+    canvas.fill_rect(Rect::from_center(center, area.width(), area.height())).unwrap();
+    // dashed lines between the circulating lanes
+    canvas.set_draw_color(Color::RGB(175, 175, 175));
+    for lane in 1..ring.lanes {
+        let d = (ring.lane_radius(lane - 1) + ring.lane_radius(lane)) / 2;
+        for t in (-d..d).step_by(30) {
+            for dash in [
+                Rect::new(center.x + t, center.y - d, 15, 2),
+                Rect::new(center.x + t, center.y + d, 15, 2),
+                Rect::new(center.x - d, center.y + t, 2, 15),
+                Rect::new(center.x + d, center.y + t, 2, 15),
+            ] {
+                canvas.fill_rect(dash).unwrap();
+            }
+        }
+    }
+    // central island, one line per row of the disc, with its kerb
+    let radius = ring.island_radius();
+    for (kerb, colour) in [(0, Color::RGB(175, 175, 175)), (3, Color::RGB(86, 125, 70))] {
+        let r = radius - kerb;
+        canvas.set_draw_color(colour);
+        for dy in -r..=r {
+            let dx = ((r * r - dy * dy) as f64).sqrt() as i32;
+            canvas.fill_rect(Rect::new(center.x - dx, center.y + dy, (dx * 2).max(1) as u32, 1)).unwrap();
+        }
+    }
+    // give way lines across the arms, at the edge of the ring, mirrored left to right when driving on the left
+    canvas.set_draw_color(Color::RGB(175, 175, 175));
+    let (half_ring, half_road) = (area.width() as i32 / 2, ROAD_WIDTH as i32 / 2);
+    let mirror = |r: Rect| match ring.side {
+        layout::DrivingSide::Right => r,
+        layout::DrivingSide::Left => Rect::new(2 * center.x - r.right(), r.y(), r.width(), r.height()),
+    };
+    for line in [
+        Rect::new(center.x - half_road, center.y - half_ring - 4, half_road as u32, 3),
+        Rect::new(center.x, center.y + half_ring + 1, half_road as u32, 3),
+        Rect::new(center.x + half_ring + 1, center.y - half_road, 3, half_road as u32),
+        Rect::new(center.x - half_ring - 4, center.y, 3, half_road as u32),
+    ] {
+        canvas.fill_rect(mirror(line)).unwrap();
     }
 }
 fn draw_road(canvas: &mut Canvas<Window>) {
@@ -484,10 +1326,6 @@ fn draw_road(canvas: &mut Canvas<Window>) {
 
     canvas.set_blend_mode(sdl2::render::BlendMode::Blend);
 
-    // Clear canvas with background color
-    canvas.set_draw_color(Color::RGB(86, 125, 70));
-    canvas.clear();
-
     // Draw vertical and horizontal roads
     canvas.set_draw_color(Color::RGB(45, 45, 45));
     canvas
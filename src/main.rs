@@ -1,7 +1,182 @@
 use smart_road::*;
+use smart_road::demand::{DemandProfile, PoissonDemand};
+use smart_road::scenario::{OdMatrix, Scenario};
+use smart_road::replay::Replay;
+use smart_road::osm::{self, Bbox};
+use smart_road::safety::SafetyConfig;
+use smart_road::sumo;
+use smart_road::control::Control;
+use smart_road::platoon::PlatoonConfig;
+use smart_road::layout::{DrivingSide, Layout};
+use std::time::Duration;
 
 // Main function to start the simulation
+// options: --seed <n>, --log <file> (with --log-states to add every vehicle state), --replay <file> (not with --log),
+// --od <file> to draw the approaches and turns from an origin-destination matrix (Poisson arrivals during the auto-spawn loop),
+// --poisson <veh/min> for Poisson arrivals on every approach during the auto-spawn loop,
+// with a morning peak tripling the demand of --am-peak <north|south|east|west> during the middle half of the loop,
+// --ttc <s>, --pet <s>, --drac <px/s²> to change the thresholds of the logged conflicts,
+// --record <dir> to render off-screen and write the frames to numbered PNGs, for at most --record-max <s> simulated seconds (600),
+// --osm <file> to simulate the junctions of an OpenStreetMap extract, within --bbox <min_lon,min_lat,max_lon,max_lat>,
+// --sumo-net <file> to simulate the junctions of a SUMO network, with the vehicles of --sumo-routes <file>,
+// --roundabout to replace the crossing of every intersection by a roundabout, of --ring-lanes <1|2> circulating lanes (1),
+// --t-junction <north|east|south|west> to remove that arm from every intersection,
+// --left-hand to drive on the left of the roads (UK, Japan...),
+// --control <heuristic|all-way-stop|two-way-stop[:ns|:ew]|priority-right> to change the rules of every intersection,
+// --platooning to let the connected vehicles of a lane cross the intersections in platoons
 fn main() {
-    let mut simulation = Simulation::new();
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    // the window is created headless when recording, before the other options are applied
+    let record = args.iter().position(|a| a == "--record").map(|i| args.get(i + 1).expect("--record needs a directory").clone());
+    let mut simulation = if record.is_some() { Simulation::headless() } else { Simulation::new() };
+    let mut args = args.into_iter();
+    let mut log = None;
+    let mut log_states = false;
+    let mut replay = None;
+    let mut od = None;
+    let mut poisson = None;
+    let mut am_peak = None;
+    let mut record_max = None;
+    let mut safety = SafetyConfig::default();
+    let mut osm_path = None;
+    let mut bbox = None;
+    let mut sumo_net = None;
+    let mut sumo_routes = None;
+    let mut roundabout = None;
+    let mut missing_arm = None;
+    let mut left_hand = false;
+    let mut control = None;
+    let mut platooning = false;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--seed" => {
+                let seed = args.next().and_then(|s| s.parse().ok()).expect("--seed needs a number");
+                simulation = simulation.with_seed(seed);
+            }
+            "--log" => log = Some(args.next().expect("--log needs a file")),
+            "--log-states" => log_states = true,
+            "--replay" => {
+                let path = args.next().expect("--replay needs a file");
+                replay = Some(Replay::load(&path).expect("Failed to load run log"));
+            }
+            "--od" => {
+                let path = args.next().expect("--od needs a file");
+                od = Some(OdMatrix::load(&path).expect("Failed to load the OD matrix"));
+            }
+            "--poisson" => poisson = Some(args.next().and_then(|s| s.parse().ok()).expect("--poisson needs a number")),
+            "--am-peak" => am_peak = Some(approach(args.next(), "--am-peak")),
+            "--record" => {
+                args.next();
+            }
+            "--record-max" => {
+                let seconds: f64 = args.next().and_then(|s| s.parse().ok()).expect("--record-max needs a number");
+                record_max = Some(Duration::from_secs_f64(seconds));
+            }
+            "--osm" => osm_path = Some(args.next().expect("--osm needs a file")),
+            "--bbox" => {
+                let text = args.next().expect("--bbox needs min_lon,min_lat,max_lon,max_lat");
+                bbox = Some(Bbox::parse(&text).expect("Invalid bbox"));
+            }
+            "--sumo-net" => sumo_net = Some(args.next().expect("--sumo-net needs a file")),
+            "--sumo-routes" => sumo_routes = Some(args.next().expect("--sumo-routes needs a file")),
+            "--roundabout" => roundabout = Some(roundabout.unwrap_or(1)),
+            "--ring-lanes" => {
+                let lanes = args.next().and_then(|s| s.parse().ok()).filter(|l| (1..=2).contains(l));
+                roundabout = Some(lanes.expect("--ring-lanes needs 1 or 2"));
+            }
+            "--left-hand" => left_hand = true,
+            "--platooning" => platooning = true,
+            "--control" => {
+                let text = args.next().expect("--control needs a rule");
+                control = Some(Control::parse(&text).expect("Invalid control"));
+            }
+            "--t-junction" => missing_arm = Some(approach(args.next(), "--t-junction")),
+            "--ttc" => safety.ttc = args.next().and_then(|s| s.parse().ok()).expect("--ttc needs a number"),
+            "--pet" => safety.pet = args.next().and_then(|s| s.parse().ok()).expect("--pet needs a number"),
+            "--drac" => safety.drac = args.next().and_then(|s| s.parse().ok()).expect("--drac needs a number"),
+            _ => eprintln!("Unknown option: {}", arg),
+        }
+    }
+    if let Some(od) = &od {
+        simulation = simulation.with_scenario(Scenario::new(od.clone()));
+    }
+    if poisson.is_some() || am_peak.is_some() {
+        if od.is_some() && poisson.is_some() {
+            eprintln!("--poisson is ignored, the rates come from --od");
+        }
+        // the default rate is the one of the fixed demand, 1 vehicle every 300 ms spread over the approaches
+        let mut demand = match od {
+            Some(od) => PoissonDemand::from_od(od),
+            None => PoissonDemand::new(poisson.unwrap_or(50.0)),
+        };
+        if let Some(approach) = am_peak {
+            demand = demand.with_profile(DemandProfile::am_peak(approach, Duration::from_secs(60)));
+        }
+        simulation = simulation.with_demand(Box::new(demand));
+    }
+    simulation = simulation.with_safety(safety);
+    let mut network = osm_path.map(|path| osm::load(&path, bbox).expect("Failed to import the OSM extract"));
+    let net = sumo_net.map(|path| sumo::load_net(&path).expect("Failed to import the SUMO network"));
+    if let Some(net) = &net {
+        network = Some(net.network.clone());
+    }
+    if let Some(arm) = missing_arm {
+        let mut t_network = network.unwrap_or_default();
+        for node in 0..t_network.nodes.len() {
+            t_network = t_network.with_layout(node, Layout::t_junction(arm));
+        }
+        network = Some(t_network);
+    }
+    if left_hand {
+        network = Some(network.unwrap_or_default().with_driving_side(DrivingSide::Left));
+    }
+    if let Some(control) = control {
+        network = Some(network.unwrap_or_default().with_controls(control));
+    }
+    if let Some(lanes) = roundabout {
+        network = Some(network.unwrap_or_default().with_roundabouts(lanes));
+    }
+    if let Some(network) = network {
+        simulation = simulation.with_network(network);
+    }
+    // after the scenario, which has its own platooning
+    if platooning {
+        simulation = simulation.with_platooning(PlatoonConfig::default());
+    }
+    if let Some(net) = net {
+        if let Some(path) = sumo_routes {
+            replay = Some(net.load_routes(&path).expect("Failed to import the SUMO routes"));
+        }
+    } else if sumo_routes.is_some() {
+        eprintln!("--sumo-routes needs --sumo-net");
+    }
+    // a played back run draws no spawn to write, the log would hold a header and an end only
+    if log.is_some() && replay.is_some() {
+        panic!("--log cannot record a played back run (--replay or --sumo-routes)");
+    }
+    // played back on the network, once it is set
+    if let Some(replay) = replay {
+        simulation = simulation.with_replay(replay).expect("The run log does not fit the network");
+    }
+    if let Some(path) = log {
+        simulation = simulation.with_recorder(&path, log_states).expect("Failed to create run log");
+    }
+    if let Some(dir) = record {
+        simulation = simulation.with_capture(&dir).expect("Failed to create the frame directory");
+        if let Some(max) = record_max {
+            simulation = simulation.with_capture_max(max);
+        }
+    }
     simulation.run();
-}
\ No newline at end of file
+}
+
+// Approach named by the argument of `option`
+fn approach(arg: Option<String>, option: &str) -> Direction {
+    match arg.as_deref() {
+        Some("north") => Direction::North,
+        Some("east") => Direction::East,
+        Some("south") => Direction::South,
+        Some("west") => Direction::West,
+        _ => panic!("{} needs north, east, south or west", option),
+    }
+}
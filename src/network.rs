@@ -0,0 +1,345 @@
+use rand::Rng;
+use sdl2::rect::{Point, Rect};
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use crate::control::Control;
+use crate::layout::{ArmRoad, DrivingSide, Layout};
+use crate::scenario::TURNS;
+use crate::{Direction, Sector, ROAD_NUMBER, SCREEN_HEIGHT, SCREEN_WIDTH};
+
+// Largest number of intersections on a side of an imported network, the whole map is one texture
+pub const MAX_SPAN: i32 = 8;
+
+// Direction taken after turning to `turn` when driving on `route`
+pub fn turned(route: Direction, turn: Direction) -> Direction {
+    match (route, turn) {
+        (Direction::North, Direction::Left) | (Direction::South, Direction::Right) => Direction::West,
+        (Direction::North, Direction::Right) | (Direction::South, Direction::Left) => Direction::East,
+        (Direction::East, Direction::Left) | (Direction::West, Direction::Right) => Direction::North,
+        (Direction::East, Direction::Right) | (Direction::West, Direction::Left) => Direction::South,
+        _ => route,
+    }
+}
+// Route of the vehicles driving the other way on the same road
+pub fn opposite(route: Direction) -> Direction {
+    turned(turned(route, Direction::Left), Direction::Left)
+}
+
+// Side where a point `(dx, dy)` away lies (x to the east, y to the south), along its main direction:
+// the route of the vehicles coming from it
+pub fn side_of(dx: f64, dy: f64) -> Direction {
+    if dx.abs() >= dy.abs() {
+        if dx > 0.0 { Direction::East } else { Direction::West }
+    } else if dy > 0.0 {
+        Direction::South
+    } else {
+        Direction::North
+    }
+}
+
+// Intersection of the network, on a grid of screen sized tiles
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, Debug, PartialEq)]
+pub struct Node {
+    pub column: i32,
+    pub row: i32,
+    pub sector: Sector,     // geometry of the intersection, in map coordinates
+}
+
+// Road between two neighbour intersections, one per way
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, Debug, PartialEq, Copy)]
+pub struct Link {
+    pub from: usize,
+    pub to: usize,
+    pub route: Direction,   // route of the vehicles driving from `from` to `to`
+    pub lanes: u32,         // lanes of the road in this way
+}
+
+// One-way road between two points of an imported geometry
+#[derive(Clone, Debug, PartialEq, Copy)]
+pub struct Road {
+    pub from: usize,
+    pub to: usize,
+    pub lanes: u32,
+}
+
+// Intersections (nodes) joined by multi-lane roads (links)
+// every intersection keeps the layout of the single one, centred on its own tile,
+// vehicles leaving a tile on a link carry on to the next intersection, otherwise they leave the network
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, Debug, PartialEq)]
+pub struct Network {
+    pub nodes: Vec<Node>,
+    pub links: Vec<Link>,
+    #[cfg_attr(feature = "serde", serde(with = "crate::snapshot::rect"))]
+    pub map: Rect,          // tiles of every intersection
+}
+impl Default for Network {
+    // The single intersection of the screen
+    fn default() -> Self {
+        Network::grid(1, 1)
+    }
+}
+impl Network {
+    // Intersections at the given (column, row) of the grid, without roads between them
+    // the map starts at the tile (0, 0), cells are not negative
+    pub fn new(cells: &[(i32, i32)]) -> Self {
+        assert!(cells.iter().all(|&(column, row)| column >= 0 && row >= 0), "negative cell in the network");
+        let right = cells.iter().map(|c| c.0).max().unwrap_or(0);
+        let bottom = cells.iter().map(|c| c.1).max().unwrap_or(0);
+        let map = Rect::new(0, 0, (right + 1) as u32 * SCREEN_WIDTH, (bottom + 1) as u32 * SCREEN_HEIGHT);
+        let nodes = cells
+            .iter()
+            .map(|&(column, row)| Node {
+                column,
+                row,
+                sector: Sector::around(Network::cell_tile(column, row).center(), map),
+            })
+            .collect();
+        Network {
+            nodes,
+            links: Vec::new(),
+            map,
+        }
+    }
+    // Every cell of a grid of `columns` x `rows` intersections, each one joined to its neighbours
+    pub fn grid(columns: i32, rows: i32) -> Self {
+        let cells: Vec<(i32, i32)> = (0..rows).flat_map(|row| (0..columns).map(move |column| (column, row))).collect();
+        let mut network = Network::new(&cells);
+        for a in 0..network.nodes.len() {
+            for b in 0..network.nodes.len() {
+                let (from, to) = (&network.nodes[a], &network.nodes[b]);
+                if (from.column - to.column).abs() + (from.row - to.row).abs() == 1 && a < b {
+                    network.connect(a, b);
+                }
+            }
+        }
+        network
+    }
+    // Intersections at `points` (x to the east, y to the south, in any unit) joined by `roads`,
+    // snapped on the grid by following the roads from the busiest point:
+    // a road goes one cell in its main direction (east-west or north-south),
+    // points falling on a taken cell (or beyond MAX_SPAN) and roads between non neighbour cells are dropped
+    // returns the network, the intersection of each point and the number of points and roads dropped
+    pub fn snap(points: &[(f64, f64)], roads: &[Road]) -> (Network, Vec<Option<usize>>, usize) {
+        if points.is_empty() {
+            return (Network::new(&[]), Vec::new(), roads.len());
+        }
+        let touching = |p: usize| roads.iter().filter(move |r| r.from == p || r.to == p);
+        let start = (0..points.len()).rev().max_by_key(|&p| touching(p).count()).unwrap();
+        let mut cells: HashMap<usize, (i32, i32)> = HashMap::from([(start, (0, 0))]);
+        let mut taken: HashSet<(i32, i32)> = HashSet::from([(0, 0)]);
+        let mut bounds = (0, 0, 0, 0);    // min column, max column, min row, max row
+        let mut queue = VecDeque::from([start]);
+        while let Some(p) = queue.pop_front() {
+            for road in touching(p) {
+                let other = if road.from == p { road.to } else { road.from };
+                if cells.contains_key(&other) {
+                    continue;
+                }
+                let ((x0, y0), (x1, y1)) = (points[p], points[other]);
+                let (column, row) = cells[&p];
+                let cell = if (x1 - x0).abs() >= (y1 - y0).abs() {
+                    (column + if x1 > x0 { 1 } else { -1 }, row)
+                } else {
+                    (column, row + if y1 > y0 { 1 } else { -1 })
+                };
+                let grown = (bounds.0.min(cell.0), bounds.1.max(cell.0), bounds.2.min(cell.1), bounds.3.max(cell.1));
+                if taken.contains(&cell) || grown.1 - grown.0 >= MAX_SPAN || grown.3 - grown.2 >= MAX_SPAN {
+                    continue;
+                }
+                bounds = grown;
+                taken.insert(cell);
+                cells.insert(other, cell);
+                queue.push_back(other);
+            }
+        }
+        // Intersections in the order of the points, moved to start at the cell (0, 0)
+        let mut nodes = vec![None; points.len()];
+        let mut grid = Vec::new();
+        for (p, node) in nodes.iter_mut().enumerate() {
+            if let Some(&(column, row)) = cells.get(&p) {
+                *node = Some(grid.len());
+                grid.push((column - bounds.0, row - bounds.2));
+            }
+        }
+        let mut network = Network::new(&grid);
+        let mut dropped = points.len() - grid.len();
+        for road in roads {
+            let (Some(a), Some(b)) = (nodes[road.from], nodes[road.to]) else {
+                dropped += 1;
+                continue;
+            };
+            if (grid[a].0 - grid[b].0).abs() + (grid[a].1 - grid[b].1).abs() != 1 {
+                dropped += 1;
+                continue;
+            }
+            network.link(a, b, road.lanes);
+        }
+        (network, nodes, dropped)
+    }
+    // Replace the crossing of an intersection by a roundabout of `lanes` circulating lanes
+    pub fn with_roundabout(mut self, node: usize, lanes: u32) -> Self {
+        self.nodes[node].sector = self.nodes[node].sector.clone().with_roundabout(lanes);
+        self
+    }
+    // Other arms and lanes at an intersection
+    pub fn with_layout(mut self, node: usize, layout: Layout) -> Self {
+        self.nodes[node].sector = self.nodes[node].sector.clone().with_layout(layout);
+        self
+    }
+    // Arms and lanes of an imported intersection from the roads meeting at its junction,
+    // the links to the other intersections replacing the roads on their side
+    pub fn with_roads(self, node: usize, roads: &[ArmRoad]) -> Self {
+        let linked = |route: Direction| {
+            self.links
+                .iter()
+                .any(|l| (l.to == node && l.route == route) || (l.from == node && l.route == opposite(route)))
+        };
+        let mut all: Vec<ArmRoad> = roads.iter().copied().filter(|r| !linked(r.route)).collect();
+        for link in &self.links {
+            if link.to == node {
+                all.push(ArmRoad { route: link.route, lanes_in: link.lanes, lanes_out: 0 });
+            }
+            if link.from == node {
+                all.push(ArmRoad { route: opposite(link.route), lanes_in: 0, lanes_out: link.lanes });
+            }
+        }
+        let layout = Layout::from_roads(&all);
+        self.with_layout(node, layout)
+    }
+    // Other rules for entering an intersection
+    pub fn with_control(mut self, node: usize, control: Control) -> Self {
+        self.nodes[node].sector = self.nodes[node].sector.clone().with_control(control);
+        self
+    }
+    // Same rules at every intersection
+    pub fn with_controls(mut self, control: Control) -> Self {
+        for node in 0..self.nodes.len() {
+            self = self.with_control(node, control);
+        }
+        self
+    }
+    // Drive on `side` of the roads at every intersection
+    pub fn with_driving_side(mut self, side: DrivingSide) -> Self {
+        for node in 0..self.nodes.len() {
+            let layout = self.nodes[node].sector.layout.clone().with_side(side);
+            self = self.with_layout(node, layout);
+        }
+        self
+    }
+    // Roundabouts of `lanes` circulating lanes at every intersection
+    pub fn with_roundabouts(mut self, lanes: u32) -> Self {
+        for node in 0..self.nodes.len() {
+            self = self.with_roundabout(node, lanes);
+        }
+        self
+    }
+    // Intersections in a row joined by a road, to study a corridor
+    pub fn corridor(length: i32) -> Self {
+        Network::grid(length, 1)
+    }
+    // Join two neighbour intersections by a road in both ways
+    pub fn connect(&mut self, a: usize, b: usize) {
+        self.link(a, b, ROAD_NUMBER);
+        self.link(b, a, ROAD_NUMBER);
+    }
+    // Join two neighbour intersections by a one-way road from `from` to `to`
+    pub fn link(&mut self, from: usize, to: usize, lanes: u32) {
+        let (a, b) = (&self.nodes[from], &self.nodes[to]);
+        // route of the vehicles going from a to b, named after the side they come from
+        let route = match (b.column - a.column, b.row - a.row) {
+            (1, 0) => Direction::West,
+            (-1, 0) => Direction::East,
+            (0, 1) => Direction::North,
+            (0, -1) => Direction::South,
+            _ => panic!("intersections {} and {} are not neighbours", from, to),
+        };
+        if !self.links.iter().any(|l| l.from == from && l.to == to) {
+            self.links.push(Link { from, to, route, lanes });
+        }
+    }
+
+    fn cell_tile(column: i32, row: i32) -> Rect {
+        Rect::new(column * SCREEN_WIDTH as i32, row * SCREEN_HEIGHT as i32, SCREEN_WIDTH, SCREEN_HEIGHT)
+    }
+    // Screen sized area around an intersection
+    pub fn tile(&self, node: usize) -> Rect {
+        Network::cell_tile(self.nodes[node].column, self.nodes[node].row)
+    }
+    // Whether a vehicle driving on `route` at `position` went past the far edge of the tile of `node`
+    pub fn has_left(&self, node: usize, route: Direction, position: Point) -> bool {
+        let tile = self.tile(node);
+        match route {
+            Direction::North => position.y >= tile.bottom(),
+            Direction::South => position.y < tile.top(),
+            Direction::East => position.x < tile.left(),
+            Direction::West => position.x >= tile.right(),
+            _ => false,
+        }
+    }
+    // Intersection reached by driving on `route` out of `node`, None when the road leaves the network
+    // or the next intersection has no arm on that side
+    pub fn next(&self, node: usize, route: Direction) -> Option<usize> {
+        self.links
+            .iter()
+            .find(|l| l.from == node && l.route == route)
+            .map(|l| l.to)
+            .filter(|&to| self.nodes[to].sector.layout.arm(route).is_some())
+    }
+    // Whether vehicles can leave `node` on `route`: through an arm of the intersection, to the next one,
+    // or out of the network when no road of the network comes from that side (one-way roads)
+    pub fn can_leave(&self, node: usize, route: Direction) -> bool {
+        self.nodes[node].sector.layout.arm(opposite(route)).is_some()
+            && (self.next(node, route).is_some()
+                || !self.links.iter().any(|l| l.to == node && l.route == opposite(route)))
+    }
+    // Intersections where vehicles of `route` enter the network, from an arm without road to another intersection
+    pub fn entries(&self, route: Direction) -> Vec<usize> {
+        (0..self.nodes.len())
+            .filter(|&node| {
+                self.nodes[node].sector.layout.arm(route).is_some() && !self.links.iter().any(|l| {
+                    (l.to == node && l.route == route) || (l.from == node && l.route == opposite(route))
+                })
+            })
+            .collect()
+    }
+    // Whether `area` touches the box of an intersection
+    pub fn in_intersection(&self, area: Rect) -> bool {
+        self.nodes.iter().any(|n| n.sector.in_intersect.has_intersection(area))
+    }
+
+    // Turns at the intersections after `node` for a vehicle entering it on `route` and turning to `turn`,
+    // drawn at random until the vehicle leaves the network (or has crossed every intersection twice)
+    pub fn random_path<R: Rng + ?Sized>(&self, node: usize, route: Direction, turn: Direction, rng: &mut R) -> Vec<Direction> {
+        let mut path = Vec::new();
+        let mut route = turned(route, turn);
+        let mut node = node;
+        while let Some(next) = self.next(node, route) {
+            if path.len() >= self.nodes.len() * 2 {
+                break;
+            }
+            // only the turns of the lanes of the arm, never into a one-way road the wrong way,
+            // the path ends at an intersection where every turn would
+            let layout = &self.nodes[next].sector.layout;
+            let allowed: Vec<Direction> = TURNS
+                .into_iter()
+                .filter(|&t| layout.allows(route, t) && self.can_leave(next, turned(route, t)))
+                .collect();
+            if allowed.is_empty() {
+                break;
+            }
+            let turn = allowed[rng.gen_range(0..allowed.len())];
+            path.push(turn);
+            route = turned(route, turn);
+            node = next;
+        }
+        path
+    }
+
+    // Centre of an intersection
+    pub fn center(&self, node: usize) -> Point {
+        self.tile(node).center()
+    }
+}
@@ -0,0 +1,268 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::layout::ArmRoad;
+use crate::network::{side_of, Network, Road};
+use crate::xml::{attribute, events, XmlEvent};
+use crate::ROAD_NUMBER;
+
+// Roads driven by cars, with their link roads (`primary_link`...)
+const CAR_HIGHWAYS: [&str; 9] = [
+    "motorway", "trunk", "primary", "secondary", "tertiary", "unclassified", "residential", "living_street", "road",
+];
+
+// Area kept from an extract, in degrees, ordered like the OSM API: min lon, min lat, max lon, max lat
+#[derive(Clone, Debug, PartialEq, Copy)]
+pub struct Bbox {
+    pub min_lon: f64,
+    pub min_lat: f64,
+    pub max_lon: f64,
+    pub max_lat: f64,
+}
+impl Bbox {
+    // "min_lon,min_lat,max_lon,max_lat"
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let values: Vec<f64> = text
+            .split(',')
+            .map(|v| v.trim().parse().map_err(|_| format!("bad bbox value: {}", v)))
+            .collect::<Result<_, _>>()?;
+        let [min_lon, min_lat, max_lon, max_lat] = values[..] else {
+            return Err("a bbox is min_lon,min_lat,max_lon,max_lat".to_string());
+        };
+        Ok(Bbox { min_lon, min_lat, max_lon, max_lat })
+    }
+    fn contains(&self, (lon, lat): (f64, f64)) -> bool {
+        (self.min_lon..=self.max_lon).contains(&lon) && (self.min_lat..=self.max_lat).contains(&lat)
+    }
+}
+
+// Position (lon, lat) of the nodes of the extract
+type Positions = HashMap<u64, (f64, f64)>;
+
+// Car road of the extract
+struct Way {
+    nodes: Vec<u64>,
+    oneway: i8,         // 1 one-way along the nodes, -1 against them, 0 both ways
+    lanes: (u32, u32),  // lanes along and against the nodes
+}
+
+// Road between two junctions, along a way
+struct Edge {
+    from: u64,
+    to: u64,
+    way: usize,
+}
+
+// Network of the junctions of a `.osm` extract, within `bbox` if given
+pub fn load(path: &str, bbox: Option<Bbox>) -> Result<Network, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    parse(&text, bbox)
+}
+
+// Junctions (nodes where three road segments or more meet) become intersections snapped on the grid,
+// the roads between them links
+pub fn parse(text: &str, bbox: Option<Bbox>) -> Result<Network, String> {
+    let (positions, ways) = read(text)?;
+    let inside = |id: &u64| positions.get(id).is_some_and(|&p| bbox.is_none_or(|b| b.contains(p)));
+
+    // Segments kept, and the neighbours of every node
+    let mut neighbours: HashMap<u64, HashSet<u64>> = HashMap::new();
+    for way in &ways {
+        for pair in way.nodes.windows(2) {
+            if inside(&pair[0]) && inside(&pair[1]) && pair[0] != pair[1] {
+                neighbours.entry(pair[0]).or_default().insert(pair[1]);
+                neighbours.entry(pair[1]).or_default().insert(pair[0]);
+            }
+        }
+    }
+    let junctions: HashSet<u64> = neighbours.iter().filter(|(_, n)| n.len() >= 3).map(|(&id, _)| id).collect();
+    if junctions.is_empty() {
+        return Err("no road junction in the extract".to_string());
+    }
+
+    // Roads between consecutive junctions of a way, cut where the way leaves the bbox
+    let mut edges = Vec::new();
+    for (w, way) in ways.iter().enumerate() {
+        let mut last = None;
+        for id in &way.nodes {
+            if !inside(id) {
+                last = None;
+            } else if junctions.contains(id) {
+                if let Some(from) = last.filter(|from| from != id) {
+                    edges.push(Edge { from, to: *id, way: w });
+                }
+                last = Some(*id);
+            }
+        }
+    }
+
+    // Junctions in the order of their OSM id, east is +x and south is +y
+    let mut sorted: Vec<u64> = junctions.iter().copied().collect();
+    sorted.sort_unstable();
+    let index: HashMap<u64, usize> = sorted.iter().enumerate().map(|(i, id)| (*id, i)).collect();
+    let project = |id: &u64| {
+        let (lon, lat) = positions[id];
+        (lon * lat.to_radians().cos(), -lat)
+    };
+    let points: Vec<(f64, f64)> = sorted.iter().map(project).collect();
+    let mut roads = Vec::new();
+    for edge in &edges {
+        let (a, b) = (index[&edge.from], index[&edge.to]);
+        let way = &ways[edge.way];
+        if way.oneway >= 0 {
+            roads.push(Road { from: a, to: b, lanes: way.lanes.0 });
+        }
+        if way.oneway <= 0 {
+            roads.push(Road { from: b, to: a, lanes: way.lanes.1 });
+        }
+    }
+
+    // Segments of the ways at every junction, with their lanes toward and away from it
+    let mut arms: Vec<Vec<ArmRoad>> = vec![Vec::new(); sorted.len()];
+    for way in &ways {
+        let (along, against) = (
+            if way.oneway >= 0 { way.lanes.0 } else { 0 },
+            if way.oneway <= 0 { way.lanes.1 } else { 0 },
+        );
+        for (k, id) in way.nodes.iter().enumerate() {
+            let Some(&j) = index.get(id) else { continue };
+            let (x, y) = project(id);
+            let before = k.checked_sub(1).map(|k| (way.nodes[k], along, against));
+            let after = way.nodes.get(k + 1).map(|&n| (n, against, along));
+            for (neighbour, lanes_in, lanes_out) in before.into_iter().chain(after) {
+                if neighbour != *id && inside(&neighbour) {
+                    let (nx, ny) = project(&neighbour);
+                    arms[j].push(ArmRoad { route: side_of(nx - x, ny - y), lanes_in, lanes_out });
+                }
+            }
+        }
+    }
+    let (mut network, placed, dropped) = Network::snap(&points, &roads);
+    if dropped > 0 {
+        eprintln!("OSM import: {} junctions or roads did not fit on the grid and were dropped", dropped);
+    }
+    for (j, node) in placed.into_iter().enumerate() {
+        if let Some(node) = node {
+            network = network.with_roads(node, &arms[j]);
+        }
+    }
+    Ok(network)
+}
+
+// Position (lon, lat) of every node and the car roads of the extract
+fn read(text: &str) -> Result<(Positions, Vec<Way>), String> {
+    let mut positions = HashMap::new();
+    let mut ways = Vec::new();
+    // way being read: its nodes and tags
+    let mut current: Option<(Vec<u64>, HashMap<String, String>)> = None;
+    for event in events(text)? {
+        match event {
+            XmlEvent::Start { name, attributes } => match name.as_str() {
+                "node" => {
+                    let id = attribute(&attributes, "id").and_then(|v| v.parse().ok());
+                    let lat = attribute(&attributes, "lat").and_then(|v| v.parse().ok());
+                    let lon = attribute(&attributes, "lon").and_then(|v| v.parse().ok());
+                    if let (Some(id), Some(lat), Some(lon)) = (id, lat, lon) {
+                        positions.insert(id, (lon, lat));
+                    }
+                }
+                "way" => current = Some((Vec::new(), HashMap::new())),
+                "nd" => {
+                    if let (Some((nodes, _)), Some(id)) = (current.as_mut(), attribute(&attributes, "ref").and_then(|v| v.parse().ok())) {
+                        nodes.push(id);
+                    }
+                }
+                "tag" => {
+                    if let (Some((_, tags)), Some(k), Some(v)) = (current.as_mut(), attribute(&attributes, "k"), attribute(&attributes, "v")) {
+                        tags.insert(k.to_string(), v.to_string());
+                    }
+                }
+                _ => {}
+            },
+            XmlEvent::End { name } if name == "way" => {
+                if let Some((nodes, tags)) = current.take() {
+                    if let Some(way) = car_way(nodes, &tags) {
+                        ways.push(way);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok((positions, ways))
+}
+
+// Road of a way, if cars drive on it
+fn car_way(nodes: Vec<u64>, tags: &HashMap<String, String>) -> Option<Way> {
+    let highway = tags.get("highway")?;
+    let kind = highway.strip_suffix("_link").unwrap_or(highway);
+    if !CAR_HIGHWAYS.contains(&kind) || tags.get("access").is_some_and(|a| a == "no" || a == "private") {
+        return None;
+    }
+    let tag = |key: &str| tags.get(key).map(String::as_str);
+    let oneway = match tag("oneway") {
+        Some("yes" | "true" | "1") => 1,
+        Some("-1" | "reverse") => -1,
+        Some("no" | "false" | "0") => 0,
+        _ if kind == "motorway" || tag("junction") == Some("roundabout") => 1,
+        _ => 0,
+    };
+    let count = |key: &str| tag(key).and_then(|v| v.parse::<u32>().ok());
+    // lanes of the whole road split between the two ways when they are not tagged
+    let total = count("lanes");
+    let lanes = match oneway {
+        0 => (
+            count("lanes:forward").or(total.map(|t| t.div_ceil(2))).unwrap_or(1),
+            count("lanes:backward").or(total.map(|t| t / 2)).unwrap_or(1),
+        ),
+        _ => (total.unwrap_or(1), total.unwrap_or(1)),
+    };
+    // the geometry of an intersection holds at most ROAD_NUMBER lanes per way
+    let clamp = |l: u32| l.clamp(1, ROAD_NUMBER);
+    Some(Way {
+        nodes,
+        oneway,
+        lanes: (clamp(lanes.0), clamp(lanes.1)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Direction;
+
+    // A junction with roads to the north (2 lanes each way), east (one-way toward it) and south (2 lanes each way)
+    const T_JUNCTION: &str = r#"<osm>
+  <node id="1" lat="0" lon="0"/>
+  <node id="2" lat="0.001" lon="0"/>
+  <node id="3" lat="0" lon="0.001"/>
+  <node id="4" lat="-0.001" lon="0"/>
+  <node id="5" lat="0.001" lon="0.001"/>
+  <way id="10"><nd ref="2"/><nd ref="1"/><nd ref="4"/><tag k="highway" v="primary"/><tag k="lanes" v="4"/></way>
+  <way id="11"><nd ref="3"/><nd ref="1"/><tag k="highway" v="residential"/><tag k="oneway" v="yes"/></way>
+  <way id="12"><nd ref="2"/><nd ref="5"/><tag k="highway" v="footway"/></way>
+</osm>"#;
+
+    #[test]
+    fn junction_layout() {
+        let network = parse(T_JUNCTION, None).unwrap();
+        assert_eq!(network.nodes.len(), 1);
+        let layout = &network.nodes[0].sector.layout;
+        assert!(layout.arm(Direction::West).is_none());
+        let (north, east) = (layout.arm(Direction::North).unwrap(), layout.arm(Direction::East).unwrap());
+        assert_eq!((north.lanes.len(), north.exits), (2, 2));
+        // one lane toward the junction, none leaving it
+        assert_eq!((east.lanes.len(), east.exits), (1, 0));
+        assert!(!layout.allows(Direction::North, Direction::Left));
+        assert!(layout.allows(Direction::North, Direction::Forward));
+        assert!(layout.allows(Direction::East, Direction::Left));
+    }
+
+    #[test]
+    fn bbox_and_errors() {
+        assert_eq!(Bbox::parse("1,2,3,4").unwrap(), Bbox { min_lon: 1.0, min_lat: 2.0, max_lon: 3.0, max_lat: 4.0 });
+        assert!(Bbox::parse("1,2,3").is_err());
+        // the east road cut by the bbox leaves no junction
+        let bbox = Bbox { min_lon: -1.0, min_lat: -1.0, max_lon: 0.0005, max_lat: 1.0 };
+        assert!(parse(T_JUNCTION, Some(bbox)).is_err());
+    }
+}
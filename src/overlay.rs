@@ -0,0 +1,113 @@
+use sdl2::image::SaveSurface;
+use sdl2::pixels::{Color, PixelFormatEnum};
+use sdl2::rect::{Point, Rect};
+use sdl2::render::{Canvas, TextureQuery};
+use sdl2::surface::Surface;
+use sdl2::ttf::Font;
+use sdl2::video::Window;
+
+use crate::vehicle::Vehicle;
+
+const PANEL_PADDING: i32 = 8;
+const PANEL_COLOR: Color = Color::RGBA(20, 20, 20, 200);
+const TEXT_COLOR: Color = Color::RGB(225, 225, 255);
+
+// Draw one line of text with its top left corner at (x, y), returns its size
+pub fn draw_text(canvas: &mut Canvas<Window>, font: &Font, text: &str, x: i32, y: i32, color: Color) -> (u32, u32) {
+    if text.is_empty() {
+        return (0, font.height() as u32);
+    }
+    let surface = font
+        .render(text)
+        .blended(color)
+        .expect("Could not create surface");
+    let texture_creator = canvas.texture_creator();
+    let texture = texture_creator
+        .create_texture_from_surface(&surface)
+        .expect("Could not create texture");
+    let TextureQuery { width, height, .. } = texture.query();
+    canvas
+        .copy(&texture, None, Some(Rect::new(x, y, width, height)))
+        .expect("Render failed");
+    (width, height)
+}
+
+// Size of the panel drawn by draw_panel
+pub fn panel_size(font: &Font, lines: &[String]) -> (u32, u32) {
+    let width = lines
+        .iter()
+        .map(|line| font.size_of(line).map(|(w, _)| w).unwrap_or(0))
+        .max()
+        .unwrap_or(0);
+    (
+        width + 2 * PANEL_PADDING as u32,
+        (font.height() * lines.len() as i32 + 2 * PANEL_PADDING) as u32,
+    )
+}
+
+// Draw lines of text on a translucent panel with its top left corner at (x, y)
+pub fn draw_panel(canvas: &mut Canvas<Window>, font: &Font, lines: &[String], x: i32, y: i32) {
+    let line_height = font.height();
+    let (width, height) = panel_size(font, lines);
+    canvas.set_draw_color(PANEL_COLOR);
+    canvas.fill_rect(Rect::new(x, y, width, height)).unwrap();
+    for (i, line) in lines.iter().enumerate() {
+        draw_text(canvas, font, line, x + PANEL_PADDING, y + PANEL_PADDING + line_height * i as i32, TEXT_COLOR);
+    }
+}
+
+// Save what has been drawn on the render target (the window, not yet presented, or a texture) to a PNG
+// the pixels read are those of the whole target, whatever the scale of the drawing
+pub fn save_png(canvas: &Canvas<Window>, path: &str) -> Result<(), String> {
+    let (width, height) = canvas.output_size()?;
+    let format = PixelFormatEnum::ABGR8888;
+    let mut pixels = canvas.read_pixels(None, format)?;
+    let surface = Surface::from_data(&mut pixels, width, height, width * format.byte_size_per_pixel() as u32, format)?;
+    surface.save(path)
+}
+
+// Draw the body and hitboxes of a vehicle
+pub fn draw_hitboxes(canvas: &mut Canvas<Window>, vehicle: &Vehicle) {
+    canvas.set_draw_color(Color::RGBA(225, 225, 90,125));
+    canvas.fill_rect(vehicle.hitbox.slowdown_2).unwrap();
+    canvas.set_draw_color(Color::RGBA(90, 90, 255,125));
+    canvas.fill_rect(vehicle.hitbox.left).unwrap();
+    canvas.fill_rect(vehicle.hitbox.right).unwrap();
+    canvas.set_draw_color(Color::RGBA(125, 125, 0,125));
+    canvas.fill_rect(vehicle.hitbox.slowdown_1).unwrap();
+    canvas.set_draw_color(Color::RGBA(125, 0, 0,125));
+    canvas.fill_rect(vehicle.hitbox.closer).unwrap();
+    canvas.fill_rect(vehicle.hitbox.urgency_stop).unwrap();
+    canvas.set_draw_color(Color::RGBA(0, 125, 0,125));
+    canvas.fill_rect(vehicle.body).unwrap();
+}
+
+// Draw a trail through the past positions of a vehicle (oldest first), fading with age
+pub fn draw_trail(canvas: &mut Canvas<Window>, points: &[Point]) {
+    for (i, segment) in points.windows(2).enumerate() {
+        let alpha = (255 * (i + 1) / points.len()) as u8;
+        canvas.set_draw_color(Color::RGBA(255, 255, 255, alpha));
+        canvas.draw_line(segment[0], segment[1]).unwrap();
+    }
+}
+
+// Description of a vehicle shown by the inspect panel
+pub fn vehicle_lines(vehicle: &Vehicle) -> Vec<String> {
+    let stopped = match vehicle.stop_reason {
+        Some(reason) => reason.to_string(),
+        None => "-".to_string(),
+    };
+    vec![
+        format!("Vehicle #{}", vehicle.id()),
+        format!("Route: {:?}", vehicle.route),
+        format!("Direction: {:?}", vehicle.direction),
+        format!("Speed: {:?}", vehicle.speed),
+        format!("Velocity: {}", vehicle.velocity),
+        format!("Time: {:.2}", vehicle.stats.time.as_secs_f64()),
+        format!("Distance: {}", vehicle.stats.distance),
+        format!("Close Call: {}", vehicle.stats.close_call),
+        format!("Colision: {}", vehicle.stats.colision),
+        format!("Queue Delay: {:.2}", vehicle.stats.queue_delay.as_secs_f64()),
+        format!("Stopped: {}", stopped),
+    ]
+}
@@ -0,0 +1,216 @@
+use std::time::Duration;
+
+use crate::control::{approaching, at_line, conflicts};
+use crate::vehicle::{StopReason, Vehicle};
+use crate::{Direction, Sector, VEHICLE_HEIGHT};
+
+// Longest time between two entries into the box of a lane for the second one to count as a following headway
+pub const HEADWAY_WINDOW: Duration = Duration::from_secs(3);
+
+// Connected vehicles following each other on a lane toward an intersection form platoons,
+// admitted into the box at once and driving with short headways
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, Debug, PartialEq, Copy)]
+pub struct PlatoonConfig {
+    pub gap: i32,           // largest gap between two bodies of a platoon, px
+    pub max_size: usize,    // vehicles of a platoon, the box holds 5
+}
+impl Default for PlatoonConfig {
+    fn default() -> Self {
+        PlatoonConfig { gap: 40, max_size: 4 }
+    }
+}
+
+// Last entry into the box of a lane
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, Debug, PartialEq, Copy)]
+struct LaneEntry {
+    node: usize,
+    route: Direction,
+    lane: i32,      // lateral coordinate of the lane
+    time: Duration,
+}
+
+// Platoons admitted and the headways of the vehicles entering the boxes
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, Debug, PartialEq, Default)]
+pub struct PlatoonStats {
+    pub sizes: Vec<usize>,              // size of each platoon, when its leader entered the box
+    pub followers: usize,               // entries of vehicles following a platoon leader
+    pub platoon_headways: Vec<f64>,     // s, entry headways of the platoon followers
+    pub single_headways: Vec<f64>,      // s, entry headways of the other vehicles following one within HEADWAY_WINDOW
+    last_entries: Vec<LaneEntry>,
+}
+impl PlatoonStats {
+    // Record a vehicle entering the box of its intersection
+    pub fn enter(&mut self, vehicle: &Vehicle, vehicles: &[&Vehicle], now: Duration) {
+        let size = vehicle.platoon.map_or(1, |leader| size(leader, vehicles));
+        let follower = vehicle.platoon.is_some_and(|leader| leader != vehicle.id());
+        if vehicle.platoon == Some(vehicle.id()) {
+            self.sizes.push(size);
+        }
+        let lane = lateral(vehicle);
+        let last = self
+            .last_entries
+            .iter_mut()
+            .find(|e| e.node == vehicle.node && e.route == vehicle.route && e.lane == lane);
+        let headway = last.as_ref().map(|e| now.saturating_sub(e.time)).filter(|&h| h <= HEADWAY_WINDOW);
+        match last {
+            Some(entry) => entry.time = now,
+            None => self.last_entries.push(LaneEntry { node: vehicle.node, route: vehicle.route, lane, time: now }),
+        }
+        if follower {
+            self.followers += 1;
+        }
+        if let Some(headway) = headway {
+            if follower {
+                self.platoon_headways.push(headway.as_secs_f64());
+            } else {
+                self.single_headways.push(headway.as_secs_f64());
+            }
+        }
+    }
+}
+
+// Vehicles of the platoon of `leader`
+pub fn size(leader: u32, vehicles: &[&Vehicle]) -> usize {
+    vehicles.iter().filter(|v| v.platoon == Some(leader)).count()
+}
+// Whether a vehicle follows the leader of its platoon after it entered the box, so may enter too
+pub fn admitted(vehicle: &Vehicle, vehicles: &[&Vehicle], sector: &Sector) -> bool {
+    let Some(leader) = vehicle.platoon.filter(|&l| l != vehicle.id()) else { return false };
+    vehicles
+        .iter()
+        .find(|v| v.id() == leader)
+        .is_none_or(|l| l.node != vehicle.node || !approaching(l, sector))
+}
+// Why a vehicle at the line has to let an admitted platoon cross first, None when it does not
+pub fn must_yield(vehicle: &Vehicle, vehicles: &[&Vehicle], sector: &Sector) -> Option<StopReason> {
+    if !at_line(vehicle, sector) {
+        return None;
+    }
+    vehicles
+        .iter()
+        .filter(|o| o.platoon.is_some() && o.platoon != vehicle.platoon && o.node == vehicle.node)
+        .find(|o| approaching(o, sector) && admitted(o, vehicles, sector) && conflicts(vehicle, o, sector.layout.side))
+        .and_then(|o| o.platoon)
+        .map(StopReason::Platoon)
+}
+
+// Join the vehicles approaching an intersection to the platoon of the vehicle ahead on their lane,
+// front first, and leave the platoons once past the box
+pub fn form(vehicles: &mut [Vehicle], sectors: &[&Sector], config: PlatoonConfig) {
+    for v in vehicles.iter_mut() {
+        let sector = sectors[v.node];
+        if v.platoon.is_some() && !v.body.has_intersection(sector.in_intersect) && !approaching(v, sector) {
+            v.platoon = None;
+        }
+    }
+    let mut order: Vec<usize> = (0..vehicles.len())
+        .filter(|&i| {
+            let sector = sectors[vehicles[i].node];
+            sector.roundabout.is_none() && approaching(&vehicles[i], sector)
+        })
+        .collect();
+    order.sort_by_key(|&i| distance(&vehicles[i], sectors[vehicles[i].node]));
+    for (k, &i) in order.iter().enumerate() {
+        if vehicles[i].platoon.is_some() {
+            continue;
+        }
+        let v = &vehicles[i];
+        let sector = sectors[v.node];
+        // nearest vehicle ahead on the same lane, not yet admitted
+        let Some(&p) = order[..k].iter().rev().find(|&&j| {
+            let o = &vehicles[j];
+            o.node == v.node && o.route == v.route && lateral(o) == lateral(v)
+        }) else {
+            continue;
+        };
+        let gap = distance(v, sector) - distance(&vehicles[p], sector) - VEHICLE_HEIGHT as i32;
+        let leader = vehicles[p].platoon.unwrap_or(vehicles[p].id());
+        let all: Vec<&Vehicle> = vehicles.iter().collect();
+        let full = size(leader, &all).max(1) >= config.max_size;
+        if gap > config.gap || full || admitted(&vehicles[p], &all, sector) {
+            continue;
+        }
+        vehicles[p].platoon = Some(leader);
+        vehicles[i].platoon = Some(leader);
+    }
+}
+
+// Distance left to the box of the intersection along the route of a vehicle
+fn distance(vehicle: &Vehicle, sector: &Sector) -> i32 {
+    let area = sector.in_intersect;
+    match vehicle.route {
+        Direction::North => area.top() - vehicle.position.y,
+        Direction::South => vehicle.position.y - area.bottom(),
+        Direction::East => vehicle.position.x - area.right(),
+        _ => area.left() - vehicle.position.x,
+    }
+}
+// Lateral coordinate of the lane of a vehicle
+fn lateral(vehicle: &Vehicle) -> i32 {
+    match vehicle.route {
+        Direction::North | Direction::South => vehicle.position.x,
+        _ => vehicle.position.y,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sdl2::rect::{Point, Rect};
+
+    // Vehicle coming from the north on the lane of `direction`, its centre `y` px from the top of the screen
+    fn at(id: u32, direction: Direction, y: i32) -> Vehicle {
+        let mut vehicle = Vehicle::new(id, Direction::North, direction, 0, Duration::ZERO);
+        vehicle.position = Point::new(vehicle.position.x, y);
+        vehicle.body = Rect::from_center(vehicle.position, 25, VEHICLE_HEIGHT);
+        vehicle
+    }
+
+    #[test]
+    fn followers_join_the_vehicle_ahead() {
+        let sector = Sector::new(Rect::new(0, 0, 1000, 1000));
+        // 30 px behind the leader, then 60 px behind the follower, and one close behind on another lane
+        let mut vehicles = vec![
+            at(1, Direction::Forward, 200),
+            at(2, Direction::Forward, 120),
+            at(3, Direction::Forward, 10),
+            at(4, Direction::Left, 130),
+        ];
+        form(&mut vehicles, &[&sector], PlatoonConfig::default());
+        let platoons: Vec<Option<u32>> = vehicles.iter().map(|v| v.platoon).collect();
+        assert_eq!(platoons, vec![Some(1), Some(1), None, None]);
+        let all: Vec<&Vehicle> = vehicles.iter().collect();
+        assert_eq!(size(1, &all), 2);
+    }
+
+    #[test]
+    fn platoons_stop_at_max_size() {
+        let sector = Sector::new(Rect::new(0, 0, 1000, 1000));
+        let mut vehicles = vec![
+            at(1, Direction::Forward, 250),
+            at(2, Direction::Forward, 180),
+            at(3, Direction::Forward, 110),
+        ];
+        form(&mut vehicles, &[&sector], PlatoonConfig { gap: 40, max_size: 2 });
+        let platoons: Vec<Option<u32>> = vehicles.iter().map(|v| v.platoon).collect();
+        assert_eq!(platoons, vec![Some(1), Some(1), None]);
+    }
+
+    #[test]
+    fn leaving_the_box_splits_the_platoon() {
+        let sector = Sector::new(Rect::new(0, 0, 1000, 1000));
+        let mut vehicles = vec![at(1, Direction::Forward, 200), at(2, Direction::Forward, 120)];
+        form(&mut vehicles, &[&sector], PlatoonConfig::default());
+        assert_eq!(vehicles[1].platoon, Some(1));
+        // the leader crossed the box, the follower is admitted after it
+        vehicles[0] = at(1, Direction::Forward, sector.in_intersect.bottom() + 100);
+        vehicles[0].platoon = Some(1);
+        let all: Vec<&Vehicle> = vehicles.iter().collect();
+        assert!(admitted(&vehicles[1], &all, &sector));
+        form(&mut vehicles, &[&sector], PlatoonConfig::default());
+        assert_eq!((vehicles[0].platoon, vehicles[1].platoon), (None, Some(1)));
+    }
+}
@@ -0,0 +1,123 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use crate::demand::{approach_index, Spawn};
+use crate::Direction;
+
+// Vehicle waiting before the edge of the map for room on its lane
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, Debug, PartialEq)]
+pub struct QueuedVehicle {
+    pub spawn: Spawn,
+    pub class: usize,       // colour of the vehicle
+    pub since: Duration,    // simulation time the vehicle was generated
+    pub node: usize,            // intersection of the network it enters
+    pub lane: usize,            // lane of the arm it enters on, from the centre line
+    pub path: Vec<Direction>,   // turns at the next intersections
+}
+
+// Virtual entry queues, one per lane of each arm (intersection, approach and lane from the centre line):
+// the vehicles of the turns sharing a lane leave its queue in the order they came
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, Debug, Default)]
+pub struct EntryQueues {
+    lanes: Vec<[[VecDeque<QueuedVehicle>; 3]; 4]>,  // per intersection
+    pub max_length: usize,  // longest lane queue seen during the run
+}
+impl EntryQueues {
+    pub fn new() -> Self {
+        EntryQueues::default()
+    }
+    // Add a generated vehicle at the back of its lane
+    pub fn push(&mut self, vehicle: QueuedVehicle) {
+        if self.lanes.len() <= vehicle.node {
+            self.lanes.resize_with(vehicle.node + 1, Default::default);
+        }
+        let Some(route) = approach_index(vehicle.spawn.route) else { return };
+        let lane = &mut self.lanes[vehicle.node][route][vehicle.lane];
+        lane.push_back(vehicle);
+        self.max_length = self.max_length.max(lane.len());
+    }
+    // Vehicles at the head of every non empty lane
+    pub fn fronts(&self) -> Vec<QueuedVehicle> {
+        self.lanes
+            .iter()
+            .flatten()
+            .flatten()
+            .filter_map(|lane| lane.front().cloned())
+            .collect()
+    }
+    // Remove the head of a lane of an arm
+    pub fn pop(&mut self, node: usize, route: Direction, lane: usize) -> Option<QueuedVehicle> {
+        self.lanes.get_mut(node)?[approach_index(route)?][lane].pop_front()
+    }
+    // Number of vehicles waiting on a lane of an arm
+    pub fn lane_len(&self, node: usize, route: Direction, lane: usize) -> usize {
+        match (self.lanes.get(node), approach_index(route)) {
+            (Some(arms), Some(route)) => arms[route][lane].len(),
+            _ => 0,
+        }
+    }
+    // Number of vehicles waiting in all lanes
+    pub fn len(&self) -> usize {
+        self.lanes.iter().flatten().flatten().map(|lane| lane.len()).sum()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layout::{Arm, Layout};
+    use crate::network::Network;
+    use crate::replay::SpawnEvent;
+    use crate::world::World;
+
+    fn queued(route: Direction, direction: Direction, lane: usize, class: usize) -> QueuedVehicle {
+        QueuedVehicle {
+            spawn: Spawn { route, direction },
+            class,
+            since: Duration::ZERO,
+            node: 0,
+            lane,
+            path: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn first_in_first_out() {
+        let mut queues = EntryQueues::new();
+        // straight on and right turn on the same lane, a left turn on its own
+        queues.push(queued(Direction::North, Direction::Forward, 1, 0));
+        queues.push(queued(Direction::North, Direction::Right, 1, 1));
+        queues.push(queued(Direction::North, Direction::Left, 0, 2));
+        queues.push(queued(Direction::North, Direction::Forward, 1, 3));
+        assert_eq!(queues.len(), 4);
+        assert_eq!(queues.max_length, 3);
+        assert_eq!(queues.lane_len(0, Direction::North, 1), 3);
+        let mut fronts: Vec<usize> = queues.fronts().iter().map(|v| v.class).collect();
+        fronts.sort();
+        assert_eq!(fronts, vec![0, 2]);
+        let order: Vec<usize> = std::iter::from_fn(|| queues.pop(0, Direction::North, 1)).map(|v| v.class).collect();
+        assert_eq!(order, vec![0, 1, 3]);
+        assert_eq!(queues.len(), 1);
+        // the longest queue seen is kept
+        assert_eq!(queues.max_length, 3);
+        assert!(queues.pop(3, Direction::North, 1).is_none());
+    }
+
+    #[test]
+    fn lanes_of_the_layout() {
+        // left turns on the lane next to the centre line, straight on and right turns sharing the next one
+        let layout = Layout::cross().with_arm(Direction::North, Arm::new(vec![vec![Direction::Left], vec![Direction::Forward, Direction::Right]], 3));
+        let mut world = World::new(1).with_network(Network::default().with_layout(0, layout));
+        for (tick, direction) in [Direction::Right, Direction::Left, Direction::Forward].into_iter().enumerate() {
+            world.push(&SpawnEvent { tick: tick as u32, route: Direction::North, direction, class: 0, node: 0, path: Vec::new() });
+        }
+        assert_eq!(world.queues.lane_len(0, Direction::North, 0), 1);
+        assert_eq!(world.queues.lane_len(0, Direction::North, 1), 2);
+        assert_eq!(world.queues.pop(0, Direction::North, 1).unwrap().spawn.direction, Direction::Right);
+    }
+}
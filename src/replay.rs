@@ -0,0 +1,308 @@
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, ErrorKind, Read, Write};
+use std::path::Path;
+
+use crate::network::Network;
+use crate::vehicle::Vehicle;
+use crate::Direction;
+
+// Binary run log:
+// header  "SRRP" version:u8 seed:u64 states:u8
+// spawn   0 tick:u32 route:u8 direction:u8 class:u8 node:u16 turns:u8 then each turn:u8
+// frame   1 tick:u32 count:u16 then per vehicle id:u32 class:u8 route:u8 direction:u8 x:i32 y:i32 velocity:i8
+// end     2 tick:u32
+// all numbers are little endian
+// the network is not written, a log is played back on the network it was recorded with
+const MAGIC: &[u8; 4] = b"SRRP";
+const VERSION: u8 = 1;
+const SPAWN: u8 = 0;
+const FRAME: u8 = 1;
+const END: u8 = 2;
+
+// Vehicle entering an entry queue
+#[derive(Clone, Debug, PartialEq)]
+pub struct SpawnEvent {
+    pub tick: u32,
+    pub route: Direction,
+    pub direction: Direction,
+    pub class: u8,
+    pub node: u16,              // intersection of the network it enters
+    pub path: Vec<Direction>,   // turns at the next intersections
+}
+
+// Position of a vehicle at the end of a tick
+#[derive(Clone, Debug, PartialEq, Copy)]
+pub struct VehicleState {
+    pub id: u32,
+    pub class: u8,
+    pub route: Direction,
+    pub direction: Direction,
+    pub x: i32,
+    pub y: i32,
+    pub velocity: i32,
+}
+
+// Every vehicle on the map at the end of a tick
+#[derive(Clone, Debug, PartialEq)]
+pub struct Frame {
+    pub tick: u32,
+    pub vehicles: Vec<VehicleState>,
+}
+
+fn direction_code(direction: Direction) -> u8 {
+    match direction {
+        Direction::North => 0,
+        Direction::South => 1,
+        Direction::East => 2,
+        Direction::West => 3,
+        Direction::Left => 4,
+        Direction::Right => 5,
+        Direction::Forward => 6,
+    }
+}
+fn direction_from_code(code: u8) -> io::Result<Direction> {
+    Ok(match code {
+        0 => Direction::North,
+        1 => Direction::South,
+        2 => Direction::East,
+        3 => Direction::West,
+        4 => Direction::Left,
+        5 => Direction::Right,
+        6 => Direction::Forward,
+        _ => return Err(io::Error::new(ErrorKind::InvalidData, "unknown direction")),
+    })
+}
+
+// Writes the run log while the simulation goes
+pub struct Recorder {
+    out: BufWriter<File>,
+    pub states: bool,   // also write every vehicle state each tick
+}
+impl Recorder {
+    pub fn create<P: AsRef<Path>>(path: P, seed: u64, states: bool) -> io::Result<Self> {
+        let mut out = BufWriter::new(File::create(path)?);
+        out.write_all(MAGIC)?;
+        out.write_all(&[VERSION])?;
+        out.write_all(&seed.to_le_bytes())?;
+        out.write_all(&[states as u8])?;
+        Ok(Recorder { out, states })
+    }
+    pub fn spawn(&mut self, event: &SpawnEvent) -> io::Result<()> {
+        self.out.write_all(&[SPAWN])?;
+        self.out.write_all(&event.tick.to_le_bytes())?;
+        self.out.write_all(&[
+            direction_code(event.route),
+            direction_code(event.direction),
+            event.class,
+        ])?;
+        self.out.write_all(&event.node.to_le_bytes())?;
+        self.out.write_all(&[event.path.len() as u8])?;
+        for &turn in &event.path {
+            self.out.write_all(&[direction_code(turn)])?;
+        }
+        Ok(())
+    }
+    pub fn frame(&mut self, tick: u32, vehicles: &VecDeque<Vehicle>) -> io::Result<()> {
+        if !self.states {
+            return Ok(());
+        }
+        self.out.write_all(&[FRAME])?;
+        self.out.write_all(&tick.to_le_bytes())?;
+        self.out.write_all(&(vehicles.len() as u16).to_le_bytes())?;
+        for vehicle in vehicles {
+            let state = vehicle.state();
+            self.out.write_all(&state.id.to_le_bytes())?;
+            self.out.write_all(&[
+                state.class,
+                direction_code(state.route),
+                direction_code(state.direction),
+            ])?;
+            self.out.write_all(&state.x.to_le_bytes())?;
+            self.out.write_all(&state.y.to_le_bytes())?;
+            self.out.write_all(&[state.velocity as i8 as u8])?;
+        }
+        Ok(())
+    }
+    // Mark the end of the run and flush the log
+    pub fn finish(&mut self, tick: u32) -> io::Result<()> {
+        self.out.write_all(&[END])?;
+        self.out.write_all(&tick.to_le_bytes())?;
+        self.out.flush()
+    }
+}
+
+// A run log loaded from disk
+#[derive(Clone, Debug, PartialEq, Default)]
+pub struct Replay {
+    pub seed: u64,
+    pub spawns: Vec<SpawnEvent>,
+    pub frames: Vec<Frame>,     // empty when the run was recorded without states
+    pub end: u32,               // last tick of the run
+}
+impl Replay {
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let mut input = BufReader::new(File::open(path)?);
+        let mut magic = [0; 4];
+        input.read_exact(&mut magic)?;
+        let version = read_u8(&mut input)?;
+        if &magic != MAGIC || version != VERSION {
+            return Err(io::Error::new(ErrorKind::InvalidData, "not a smart_road run log"));
+        }
+        let seed = u64::from_le_bytes(read_array(&mut input)?);
+        read_u8(&mut input)?;
+
+        let mut replay = Replay {
+            seed,
+            ..Replay::default()
+        };
+        loop {
+            let tag = match read_u8(&mut input) {
+                Ok(tag) => tag,
+                Err(e) if e.kind() == ErrorKind::UnexpectedEof => break,
+                Err(e) => return Err(e),
+            };
+            let tick = u32::from_le_bytes(read_array(&mut input)?);
+            match tag {
+                SPAWN => {
+                    let [route, direction, class] = read_array(&mut input)?;
+                    let node = u16::from_le_bytes(read_array(&mut input)?);
+                    let mut path = Vec::new();
+                    for _ in 0..read_u8(&mut input)? {
+                        path.push(direction_from_code(read_u8(&mut input)?)?);
+                    }
+                    replay.spawns.push(SpawnEvent {
+                        tick,
+                        route: direction_from_code(route)?,
+                        direction: direction_from_code(direction)?,
+                        class,
+                        node,
+                        path,
+                    });
+                }
+                FRAME => {
+                    let count = u16::from_le_bytes(read_array(&mut input)?);
+                    let mut vehicles = Vec::with_capacity(count as usize);
+                    for _ in 0..count {
+                        let id = u32::from_le_bytes(read_array(&mut input)?);
+                        let [class, route, direction] = read_array(&mut input)?;
+                        let x = i32::from_le_bytes(read_array(&mut input)?);
+                        let y = i32::from_le_bytes(read_array(&mut input)?);
+                        let velocity = read_u8(&mut input)? as i8 as i32;
+                        vehicles.push(VehicleState {
+                            id,
+                            class,
+                            route: direction_from_code(route)?,
+                            direction: direction_from_code(direction)?,
+                            x,
+                            y,
+                            velocity,
+                        });
+                    }
+                    replay.frames.push(Frame { tick, vehicles });
+                }
+                END => replay.end = tick,
+                _ => return Err(io::Error::new(ErrorKind::InvalidData, "unknown record")),
+            }
+        }
+        Ok(replay)
+    }
+    // Check the spawns against the network the log is played back on:
+    // every vehicle enters an intersection of it, with no more turns than a path drawn on it
+    pub fn check(&self, network: &Network) -> io::Result<()> {
+        let longest = network.nodes.len() * 2;
+        for spawn in &self.spawns {
+            if spawn.node as usize >= network.nodes.len() || spawn.path.len() > longest {
+                let message = format!(
+                    "spawn at tick {} enters intersection {} with {} turns, the network has {} intersections",
+                    spawn.tick,
+                    spawn.node,
+                    spawn.path.len(),
+                    network.nodes.len()
+                );
+                return Err(io::Error::new(ErrorKind::InvalidData, message));
+            }
+        }
+        Ok(())
+    }
+    // Last tick covered by the log
+    pub fn length(&self) -> u32 {
+        let last_spawn = self.spawns.last().map_or(0, |s| s.tick);
+        let last_frame = self.frames.last().map_or(0, |f| f.tick);
+        last_spawn.max(last_frame).max(self.end)
+    }
+}
+
+fn read_u8<R: Read>(input: &mut R) -> io::Result<u8> {
+    let [byte] = read_array(input)?;
+    Ok(byte)
+}
+fn read_array<R: Read, const N: usize>(input: &mut R) -> io::Result<[u8; N]> {
+    let mut buffer = [0; N];
+    input.read_exact(&mut buffer)?;
+    Ok(buffer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("smart_road_{}_{}.srr", name, std::process::id()))
+    }
+
+    #[test]
+    fn round_trip() {
+        let path = temp_path("log");
+        let spawn = SpawnEvent {
+            tick: 12,
+            route: Direction::East,
+            direction: Direction::Left,
+            class: 3,
+            node: 2,
+            path: vec![Direction::Forward, Direction::Right],
+        };
+        let mut vehicle = Vehicle::new(7, Direction::North, Direction::Right, 1, Duration::ZERO);
+        vehicle.velocity = -2;
+        let mut recorder = Recorder::create(&path, 42, true).unwrap();
+        recorder.spawn(&spawn).unwrap();
+        recorder.frame(13, &VecDeque::from([vehicle.clone()])).unwrap();
+        recorder.finish(20).unwrap();
+
+        let replay = Replay::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(replay.seed, 42);
+        assert_eq!(replay.spawns, vec![spawn]);
+        assert_eq!(replay.frames, vec![Frame { tick: 13, vehicles: vec![vehicle.state()] }]);
+        assert_eq!(replay.end, 20);
+        assert_eq!(replay.length(), 20);
+    }
+
+    #[test]
+    fn check_against_network() {
+        let spawn = |node, turns| SpawnEvent {
+            tick: 0,
+            route: Direction::North,
+            direction: Direction::Forward,
+            class: 0,
+            node,
+            path: vec![Direction::Forward; turns],
+        };
+        let replay = Replay { spawns: vec![spawn(2, 3)], ..Replay::default() };
+        assert!(replay.check(&Network::corridor(3)).is_ok());
+        // recorded on a larger network than the one played back
+        assert_eq!(replay.check(&Network::default()).unwrap_err().kind(), ErrorKind::InvalidData);
+        let replay = Replay { spawns: vec![spawn(0, 7)], ..Replay::default() };
+        assert!(replay.check(&Network::corridor(3)).is_err());
+    }
+
+    #[test]
+    fn reject_other_files() {
+        let path = temp_path("bad");
+        std::fs::write(&path, b"SRRP\x09").unwrap();
+        let loaded = Replay::load(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.unwrap_err().kind(), ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,483 @@
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use crate::demand::APPROACHES;
+use crate::safety::Measure;
+use crate::scenario::TURNS;
+use crate::vehicle::Stats;
+use crate::world::{Sample, World};
+use crate::Direction;
+
+// HCM level of service of unsignalised intersections, from the mean control delay
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, Debug, PartialEq, Copy, Default)]
+pub enum LevelOfService {
+    #[default]
+    A,
+    B,
+    C,
+    D,
+    E,
+    F,
+}
+// Upper bounds (seconds per vehicle) of the A to E levels
+const LOS_THRESHOLDS: [f64; 5] = [10.0, 15.0, 25.0, 35.0, 50.0];
+impl LevelOfService {
+    pub fn from_delay(delay: f64) -> Self {
+        let levels = [LevelOfService::A, LevelOfService::B, LevelOfService::C, LevelOfService::D, LevelOfService::E];
+        for (level, threshold) in levels.into_iter().zip(LOS_THRESHOLDS) {
+            if delay <= threshold {
+                return level;
+            }
+        }
+        LevelOfService::F
+    }
+}
+
+// Delay, stops and level of service of one approach
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, Debug, PartialEq, Copy)]
+pub struct ApproachSummary {
+    pub approach: Direction,
+    pub arrived: usize,
+    pub control_delay: f64,     // mean, seconds per vehicle
+    pub stops: f64,             // mean stops per vehicle
+    pub los: Option<LevelOfService>,    // None when no vehicle arrived
+}
+impl ApproachSummary {
+    fn new(stats: &VecDeque<Stats>, approach: Direction) -> Self {
+        let movement: Vec<&Stats> = stats.iter().filter(|s| s.approach == approach).collect();
+        let delays: Vec<f64> = movement.iter().map(|s| s.control_delay.as_secs_f64()).collect();
+        let stops: Vec<f64> = movement.iter().map(|s| s.stops as f64).collect();
+        let control_delay = mean(&delays);
+        ApproachSummary {
+            approach,
+            arrived: movement.len(),
+            control_delay,
+            stops: mean(&stops),
+            los: (!delays.is_empty()).then(|| LevelOfService::from_delay(control_delay)),
+        }
+    }
+}
+
+// Grade of a level of service, "-" when no vehicle arrived
+fn grade(los: Option<LevelOfService>) -> String {
+    los.map_or("-".to_string(), |l| format!("{:?}", l))
+}
+
+// Distribution of one measure over a set of vehicles, all zero when the set is empty
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, Debug, PartialEq, Default, Copy)]
+pub struct Distribution {
+    pub count: usize,
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+    pub median: f64,
+    pub p95: f64,
+    pub stddev: f64,
+}
+impl Distribution {
+    pub fn new(values: &[f64]) -> Self {
+        if values.is_empty() {
+            return Distribution::default();
+        }
+        let mut sorted = values.to_vec();
+        sorted.sort_by(|a, b| a.total_cmp(b));
+        let mean = mean(&sorted);
+        let variance = sorted.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / sorted.len() as f64;
+        Distribution {
+            count: sorted.len(),
+            min: sorted[0],
+            max: sorted[sorted.len() - 1],
+            mean,
+            median: percentile(&sorted, 0.5),
+            p95: percentile(&sorted, 0.95),
+            stddev: variance.sqrt(),
+        }
+    }
+    // One line description, "-" when there is no value
+    pub fn describe(&self) -> String {
+        if self.count == 0 {
+            return "-".to_string();
+        }
+        format!(
+            "mean {:.2}  med {:.2}  p95 {:.2}  sd {:.2}  [{:.2} - {:.2}]",
+            self.mean, self.median, self.p95, self.stddev, self.min, self.max
+        )
+    }
+}
+
+// Value at rank `q` (0 to 1) of sorted values, interpolated between neighbours
+fn percentile(sorted: &[f64], q: f64) -> f64 {
+    let rank = q * (sorted.len() - 1) as f64;
+    let (low, high) = (rank.floor() as usize, rank.ceil() as usize);
+    sorted[low] + (sorted[high] - sorted[low]) * (rank - low as f64)
+}
+
+// Statistics of a run, shared by the statistics screen, the HUD and the exports
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, Debug, PartialEq, Default)]
+pub struct StatsSummary {
+    pub duration: f64,              // simulated seconds
+    pub arrived: usize,
+    pub in_progress: usize,         // vehicles still on the map
+    pub queued: usize,              // vehicles still in the entry queues
+    pub max_queue: usize,           // longest lane queue seen
+    pub throughput: f64,            // arrivals per minute
+    // vehicles which left the map
+    pub time: Distribution,
+    pub velocity: Distribution,
+    pub distance: Distribution,
+    pub queue_delay: Distribution,
+    pub control_delay: Distribution,
+    pub stops: Distribution,
+    pub stopped_time: Distribution,
+    pub colision: u32,
+    pub close_call: u32,
+    pub los: Option<LevelOfService>,    // whole intersection, None when no vehicle arrived
+    pub approaches: Vec<ApproachSummary>,
+    // vehicles still on the map, measured up to now
+    pub in_progress_time: Distribution,
+    pub in_progress_colision: u32,
+    pub in_progress_close_call: u32,
+    // worst surrogate safety measure of each vehicle pair which met, and conflicts under the thresholds
+    pub ttc: Distribution,
+    pub pet: Distribution,
+    pub drac: Distribution,
+    pub ttc_conflicts: usize,
+    pub pet_conflicts: usize,
+    pub drac_conflicts: usize,
+    // platoons admitted into the boxes, and headways of the vehicles entering them in and out of a platoon
+    pub platoon_size: Distribution,
+    pub platoon_followers: usize,
+    pub platoon_headway: Distribution,
+    pub single_headway: Distribution,
+    pub headway_gain: f64,      // %, saturation flow gained by the platoon headways over the single ones
+}
+impl StatsSummary {
+    pub fn new(world: &World) -> Self {
+        let stats = &world.stats;
+        let on_map: Vec<&Stats> = world.vehicles.iter().map(|v| &v.stats).collect();
+        let duration = world.clock().as_secs_f64();
+        let values = |f: &dyn Fn(&Stats) -> f64| stats.iter().map(f).collect::<Vec<f64>>();
+        let control_delay = values(&|s| s.control_delay.as_secs_f64());
+        let platoons = &world.platoons;
+        StatsSummary {
+            duration,
+            arrived: stats.len(),
+            in_progress: on_map.len(),
+            queued: world.queues.len(),
+            max_queue: world.queues.max_length,
+            throughput: if duration > 0.0 { stats.len() as f64 / duration * 60.0 } else { 0.0 },
+            time: Distribution::new(&values(&|s| s.time.as_secs_f64())),
+            velocity: Distribution::new(&values(&|s| s.velocity as f64)),
+            distance: Distribution::new(&values(&|s| s.distance as f64)),
+            queue_delay: Distribution::new(&values(&|s| s.queue_delay.as_secs_f64())),
+            control_delay: Distribution::new(&control_delay),
+            stops: Distribution::new(&values(&|s| s.stops as f64)),
+            stopped_time: Distribution::new(&values(&|s| s.stopped_time.as_secs_f64())),
+            colision: stats.iter().map(|s| s.colision).sum(),
+            close_call: stats.iter().map(|s| s.close_call).sum(),
+            los: (!control_delay.is_empty()).then(|| LevelOfService::from_delay(mean(&control_delay))),
+            approaches: APPROACHES.iter().map(|&a| ApproachSummary::new(stats, a)).collect(),
+            in_progress_time: Distribution::new(&on_map.iter().map(|s| s.time.as_secs_f64()).collect::<Vec<f64>>()),
+            in_progress_colision: on_map.iter().map(|s| s.colision).sum(),
+            in_progress_close_call: on_map.iter().map(|s| s.close_call).sum(),
+            ttc: Distribution::new(&world.safety.pairs().filter_map(|p| p.min_ttc).collect::<Vec<f64>>()),
+            pet: Distribution::new(&world.safety.pairs().filter_map(|p| p.min_pet).collect::<Vec<f64>>()),
+            drac: Distribution::new(&world.safety.pairs().filter_map(|p| p.max_drac).collect::<Vec<f64>>()),
+            ttc_conflicts: world.safety.count(Measure::Ttc),
+            pet_conflicts: world.safety.count(Measure::Pet),
+            drac_conflicts: world.safety.count(Measure::Drac),
+            platoon_size: Distribution::new(&platoons.sizes.iter().map(|&s| s as f64).collect::<Vec<f64>>()),
+            platoon_followers: platoons.followers,
+            platoon_headway: Distribution::new(&platoons.platoon_headways),
+            single_headway: Distribution::new(&platoons.single_headways),
+            headway_gain: if platoons.platoon_headways.is_empty() || platoons.single_headways.is_empty() {
+                0.0
+            } else {
+                (mean(&platoons.single_headways) / mean(&platoons.platoon_headways) - 1.0) * 100.0
+            },
+        }
+    }
+    // Lines shown on the summary page of the statistics screen
+    pub fn lines(&self) -> Vec<String> {
+        vec![
+            format!("Simulated: {:.1}s", self.duration),
+            format!("Arrived: {}  In Progress: {}  Queued: {}", self.arrived, self.in_progress, self.queued),
+            format!("Throughput: {:.1} /min  Max Queue: {}", self.throughput, self.max_queue),
+            format!("Time: {}", self.time.describe()),
+            format!("Velocity: {}", self.velocity.describe()),
+            format!("Queue Delay: {}", self.queue_delay.describe()),
+            format!("Control Delay: {}", self.control_delay.describe()),
+            format!("Stops: {}", self.stops.describe()),
+            format!("Stopped Time: {}", self.stopped_time.describe()),
+            format!(
+                "Level of Service: {}  ({})",
+                grade(self.los),
+                self.approaches
+                    .iter()
+                    .map(|a| format!("{:?} {}", a.approach, grade(a.los)))
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            ),
+            format!("In Progress Time: {}", self.in_progress_time.describe()),
+            format!("Colision: {} (+{} in progress)", self.colision, self.in_progress_colision),
+            format!("Close Call: {} (+{} in progress)", self.close_call, self.in_progress_close_call),
+            format!("TTC: {} ({} conflicts)", self.ttc.describe(), self.ttc_conflicts),
+            format!("PET: {} ({} conflicts)", self.pet.describe(), self.pet_conflicts),
+            format!("DRAC: {} ({} conflicts)", self.drac.describe(), self.drac_conflicts),
+            format!("Platoons: {} ({} followers)", self.platoon_size.describe(), self.platoon_followers),
+            format!(
+                "Headway: platoon {}  single {}  (+{:.1}% flow)",
+                self.platoon_headway.describe(),
+                self.single_headway.describe(),
+                self.headway_gain
+            ),
+        ]
+    }
+    // Export the distributions as CSV, one row per measure
+    pub fn write_csv<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        writeln!(out, "measure,count,min,max,mean,median,p95,stddev")?;
+        let distributions = [
+            ("time", &self.time),
+            ("velocity", &self.velocity),
+            ("distance", &self.distance),
+            ("queue_delay", &self.queue_delay),
+            ("control_delay", &self.control_delay),
+            ("stops", &self.stops),
+            ("stopped_time", &self.stopped_time),
+            ("in_progress_time", &self.in_progress_time),
+            ("ttc", &self.ttc),
+            ("pet", &self.pet),
+            ("drac", &self.drac),
+            ("platoon_size", &self.platoon_size),
+            ("platoon_headway", &self.platoon_headway),
+            ("single_headway", &self.single_headway),
+        ];
+        for (name, d) in distributions {
+            writeln!(out, "{},{},{},{},{},{},{},{}", name, d.count, d.min, d.max, d.mean, d.median, d.p95, d.stddev)?;
+        }
+        out.flush()
+    }
+    // Export the counters of the run as CSV, one row per counter
+    pub fn write_totals_csv<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        writeln!(out, "counter,value")?;
+        let counters = [
+            ("duration", self.duration),
+            ("arrived", self.arrived as f64),
+            ("in_progress", self.in_progress as f64),
+            ("queued", self.queued as f64),
+            ("max_queue", self.max_queue as f64),
+            ("throughput", self.throughput),
+            ("colision", self.colision as f64),
+            ("close_call", self.close_call as f64),
+            ("in_progress_colision", self.in_progress_colision as f64),
+            ("in_progress_close_call", self.in_progress_close_call as f64),
+            ("ttc_conflicts", self.ttc_conflicts as f64),
+            ("pet_conflicts", self.pet_conflicts as f64),
+            ("drac_conflicts", self.drac_conflicts as f64),
+            ("platoon_followers", self.platoon_followers as f64),
+            ("headway_gain", self.headway_gain),
+        ];
+        for (name, value) in counters {
+            writeln!(out, "{},{}", name, value)?;
+        }
+        out.flush()
+    }
+    // Export the level of service of each approach and of the whole intersection as CSV
+    pub fn write_los_csv<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        writeln!(out, "approach,arrived,control_delay,stops,los")?;
+        for a in &self.approaches {
+            writeln!(out, "{:?},{},{},{},{}", a.approach, a.arrived, a.control_delay, a.stops, grade(a.los))?;
+        }
+        writeln!(out, "All,{},{},{},{}", self.arrived, self.control_delay.mean, self.stops.mean, grade(self.los))?;
+        out.flush()
+    }
+    // Mean control delay, stops and level of service per approach
+    pub fn los_table(&self) -> Vec<Vec<String>> {
+        let mut rows = vec![vec![
+            "Approach".to_string(),
+            "Arrived".to_string(),
+            "Delay".to_string(),
+            "Stops".to_string(),
+            "LOS".to_string(),
+        ]];
+        for a in &self.approaches {
+            rows.push(vec![
+                format!("{:?}", a.approach),
+                a.arrived.to_string(),
+                format!("{:.2}", a.control_delay),
+                format!("{:.2}", a.stops),
+                grade(a.los),
+            ]);
+        }
+        rows.push(vec![
+            "All".to_string(),
+            self.arrived.to_string(),
+            format!("{:.2}", self.control_delay.mean),
+            format!("{:.2}", self.stops.mean),
+            grade(self.los),
+        ]);
+        rows
+    }
+}
+
+// Arrivals per minute between consecutive samples, as (time in seconds, throughput)
+pub fn throughput_series(series: &[Sample]) -> Vec<(f64, f64)> {
+    let mut points = vec![(0.0, 0.0)];
+    let mut previous: Option<&Sample> = None;
+    for sample in series {
+        let (time, arrived) = previous.map_or((0.0, 0), |p| (p.time.as_secs_f64(), p.arrived));
+        let window = sample.time.as_secs_f64() - time;
+        if window > 0.0 {
+            let rate = (sample.arrived - arrived) as f64 / window * 60.0;
+            points.push((sample.time.as_secs_f64(), rate));
+        }
+        previous = Some(sample);
+    }
+    points
+}
+
+// Vehicles inside the intersection box over time, as (time in seconds, count)
+pub fn intersection_series(series: &[Sample]) -> Vec<(f64, f64)> {
+    let mut points = vec![(0.0, 0.0)];
+    points.extend(series.iter().map(|s| (s.time.as_secs_f64(), s.in_intersection as f64)));
+    points
+}
+
+// Travel times split in `bins` equal ranges between the shortest and the longest
+pub fn travel_time_histogram(stats: &VecDeque<Stats>, bins: usize) -> Vec<(String, f64)> {
+    let times: Vec<f64> = stats.iter().map(|s| s.time.as_secs_f64()).collect();
+    if times.is_empty() || bins == 0 {
+        return Vec::new();
+    }
+    let min = times.iter().cloned().fold(f64::INFINITY, f64::min);
+    let max = times.iter().cloned().fold(0.0, f64::max);
+    let width = ((max - min) / bins as f64).max(f64::EPSILON);
+    let mut counts = vec![0.0; bins];
+    for time in times {
+        let bin = (((time - min) / width) as usize).min(bins - 1);
+        counts[bin] += 1.0;
+    }
+    counts
+        .into_iter()
+        .enumerate()
+        .map(|(i, count)| (format!("{:.0}", min + width * i as f64), count))
+        .collect()
+}
+
+// Mean control delay (seconds) of each approach
+pub fn approach_delays(stats: &VecDeque<Stats>) -> Vec<(String, f64)> {
+    APPROACHES
+        .iter()
+        .map(|&approach| {
+            let delays: Vec<f64> = stats
+                .iter()
+                .filter(|s| s.approach == approach)
+                .map(|s| s.control_delay.as_secs_f64())
+                .collect();
+            (format!("{:?}", approach), mean(&delays))
+        })
+        .collect()
+}
+
+// Arrivals per approach and turn, with the mean travel time of each approach
+pub fn approach_table(stats: &VecDeque<Stats>) -> Vec<Vec<String>> {
+    let mut rows = vec![vec![
+        "Approach".to_string(),
+        "Left".to_string(),
+        "Forward".to_string(),
+        "Right".to_string(),
+        "Total".to_string(),
+        "Mean Time".to_string(),
+    ]];
+    for approach in APPROACHES {
+        let mut row = vec![format!("{:?}", approach)];
+        for turn in TURNS {
+            let count = stats.iter().filter(|s| s.approach == approach && s.turn == turn).count();
+            row.push(count.to_string());
+        }
+        let times: Vec<f64> = stats
+            .iter()
+            .filter(|s| s.approach == approach)
+            .map(|s| s.time.as_secs_f64())
+            .collect();
+        row.push(times.len().to_string());
+        row.push(format!("{:.2}", mean(&times)));
+        rows.push(row);
+    }
+    rows
+}
+
+// Arrivals, mean travel time and mean velocity of each turn
+pub fn turn_table(stats: &VecDeque<Stats>) -> Vec<Vec<String>> {
+    let mut rows = vec![vec![
+        "Turn".to_string(),
+        "Total".to_string(),
+        "Mean Time".to_string(),
+        "Mean Velocity".to_string(),
+    ]];
+    for turn in TURNS {
+        let movement: Vec<&Stats> = stats.iter().filter(|s| s.turn == turn).collect();
+        let times: Vec<f64> = movement.iter().map(|s| s.time.as_secs_f64()).collect();
+        let velocities: Vec<f64> = movement.iter().map(|s| s.velocity as f64).collect();
+        rows.push(vec![
+            format!("{:?}", turn),
+            movement.len().to_string(),
+            format!("{:.2}", mean(&times)),
+            format!("{:.2}", mean(&velocities)),
+        ]);
+    }
+    rows
+}
+
+fn mean(values: &[f64]) -> f64 {
+    if values.is_empty() {
+        0.0
+    } else {
+        values.iter().sum::<f64>() / values.len() as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vehicle::Vehicle;
+    use std::time::Duration;
+
+    #[test]
+    fn level_at_each_threshold() {
+        use LevelOfService::*;
+        let levels = [(0.0, A), (10.0, A), (10.01, B), (15.0, B), (15.01, C), (25.0, C), (25.01, D), (35.0, D), (35.01, E), (50.0, E), (50.01, F)];
+        for (delay, level) in levels {
+            assert_eq!(LevelOfService::from_delay(delay), level, "delay {}", delay);
+        }
+    }
+
+    #[test]
+    fn no_level_without_arrivals() {
+        let delayed = |approach, seconds| {
+            let mut stats = Vehicle::new(0, approach, Direction::Forward, 0, Duration::ZERO).stats;
+            stats.control_delay = Duration::from_secs(seconds);
+            stats
+        };
+        let stats = VecDeque::from([delayed(Direction::North, 12), delayed(Direction::North, 20)]);
+        let north = ApproachSummary::new(&stats, Direction::North);
+        assert_eq!((north.arrived, north.control_delay, north.los), (2, 16.0, Some(LevelOfService::C)));
+        let south = ApproachSummary::new(&stats, Direction::South);
+        assert_eq!((south.arrived, south.los), (0, None));
+        assert_eq!(grade(south.los), "-");
+
+        let summary = StatsSummary::new(&World::new(1));
+        assert_eq!(summary.los, None);
+        assert!(summary.approaches.iter().all(|a| a.los.is_none()));
+        assert!(summary.los_table().iter().skip(1).all(|row| row.last().unwrap() == "-"));
+    }
+}
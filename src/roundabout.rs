@@ -0,0 +1,186 @@
+use sdl2::rect::{Point, Rect};
+use std::collections::VecDeque;
+
+use crate::layout::{rect_to_map, rotate_route, to_local, to_map, DrivingSide};
+use crate::vehicle::{lane_position, Vehicle};
+use crate::{Direction, ROAD_NUMBER, ROAD_WIDTH};
+
+// Distance from the centre to the middle of the outer circulating lane
+pub const RING_RADIUS: i32 = (ROAD_WIDTH / 2) as i32;
+const LANE: i32 = (ROAD_WIDTH / (ROAD_NUMBER * 2)) as i32;
+// Most circulating lanes, a third one would leave the vehicles no room to turn round the island
+pub const MAX_RING_LANES: u32 = 2;
+
+// Point where a vehicle takes a new route
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, Debug, PartialEq, Copy)]
+pub struct Waypoint {
+    #[cfg_attr(feature = "serde", serde(with = "crate::snapshot::point"))]
+    pub point: Point,
+    pub route: Direction,
+    pub entry: bool,    // the vehicle is still on its arm, yielding to the ring
+}
+
+// Roundabout replacing the crossing of an intersection: lanes circulating counter-clockwise
+// around a central island, square like the roads, entered and left by right turns
+// a vehicle entering from an arm goes round to the exit of its turn (Right: first arm, Forward: second, Left: third)
+// on the lane of that exit (the outer one for the first exit, the inner ones for the farther exits)
+// and gives way to the vehicles already on the ring
+// when driving on the left everything is mirrored: clockwise, entered by left turns, Left is the first exit
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, Debug, PartialEq)]
+pub struct Roundabout {
+    #[cfg_attr(feature = "serde", serde(with = "crate::snapshot::point"))]
+    pub center: Point,
+    pub side: DrivingSide,
+    pub lanes: u32,     // circulating lanes, 1 to MAX_RING_LANES
+}
+impl Roundabout {
+    // Single lane roundabout
+    pub fn new(center: Point, side: DrivingSide) -> Self {
+        Roundabout { center, side, lanes: 1 }
+    }
+    // Same roundabout with other circulating lanes, the island shrinking to make room for them
+    pub fn with_lanes(mut self, lanes: u32) -> Self {
+        assert!((1..=MAX_RING_LANES).contains(&lanes), "a roundabout has 1 to {} lanes", MAX_RING_LANES);
+        self.lanes = lanes;
+        self
+    }
+
+    // Outer edge of the ring
+    pub fn ring(&self) -> Rect {
+        let half = RING_RADIUS + LANE / 2;
+        Rect::from_center(self.center, (half * 2) as u32, (half * 2) as u32)
+    }
+    // Distance from the centre to the middle of a circulating lane, 0 being the outer one
+    pub fn lane_radius(&self, lane: u32) -> i32 {
+        RING_RADIUS - lane as i32 * LANE
+    }
+    // Radius of the central island
+    pub fn island_radius(&self) -> i32 {
+        self.lane_radius(self.lanes - 1) - LANE / 2
+    }
+
+    // Turns of a vehicle at `position` on the arm of `route` to leave at the exit of `turn`, ring corners included
+    pub fn waypoints(&self, route: Direction, turn: Direction, position: Point) -> VecDeque<Waypoint> {
+        // computed for the north arm in right-hand traffic, then mirrored and rotated to the arm of the vehicle
+        let origin = Point::new(0, 0);
+        let entry = self.mirror(to_local(self.center, position, route)).0;
+        let exit = lane_position(origin, Direction::North, Direction::Forward, DrivingSide::Right, origin).x;
+        // lane of the exit, or the innermost one the arm lane reaches without going round the corner
+        let exits = [Direction::Right, Direction::Forward, Direction::Left];
+        let rank = exits.iter().position(|&t| t == self.side.mirror(turn)).unwrap_or(2) as u32;
+        let mut lane = rank * self.lanes / 3;
+        while lane > 0 && entry > self.lane_radius(lane) {
+            lane -= 1;
+        }
+        let r = self.lane_radius(lane);
+        let mut local = vec![((entry, -r), Direction::East, true), ((-r, -r), Direction::North, false)];
+        match self.side.mirror(turn) {
+            Direction::Right => local.push(((-r, exit), Direction::East, false)),
+            Direction::Forward => {
+                local.push(((-r, r), Direction::West, false));
+                local.push(((exit, r), Direction::North, false));
+            }
+            _ => {
+                local.push(((-r, r), Direction::West, false));
+                local.push(((r, r), Direction::South, false));
+                local.push(((r, -exit), Direction::West, false));
+            }
+        }
+        local
+            .into_iter()
+            .map(|(point, to, entry)| Waypoint {
+                point: to_map(self.center, self.mirror(point), route),
+                route: rotate_route(self.mirror_route(to), route),
+                entry,
+            })
+            .collect()
+    }
+
+    // Whether a vehicle waiting on its arm has to give way: a vehicle is on a lane of the side of the ring it enters
+    // or about to come round the corner, or another vehicle of the arm is entering ahead of it
+    pub fn must_yield(&self, vehicle: &Vehicle, vehicles: &[&Vehicle]) -> bool {
+        if !vehicle.waypoints.front().is_some_and(|w| w.entry) {
+            return false;
+        }
+        let route = vehicle.route;
+        let (r, inner, half) = (RING_RADIUS, self.lane_radius(self.lanes - 1), LANE / 2);
+        let side = rect_to_map(self.center, self.mirror((-r - half, -r - half)), self.mirror((r + half, -inner + half)), route);
+        if !vehicle.hitbox.closer.has_intersection(side) || vehicle.body.has_intersection(side) {
+            return false;
+        }
+        let corner = rect_to_map(self.center, self.mirror((inner - half, -r)), self.mirror((r + half, -r + LANE * 2)), route);
+        let along = |v: &Vehicle| self.mirror(to_local(self.center, v.position, route)).0;
+        vehicles.iter().filter(|o| o.id() != vehicle.id()).any(|o| {
+            let entering = o.waypoints.front().is_some_and(|w| w.entry);
+            let on_ring = !entering && (o.body.has_intersection(side) || o.body.has_intersection(corner));
+            // the ring runs toward -x on the side of the arm: lower x is further on
+            let ahead = entering && o.route == vehicle.route && o.hitbox.closer.has_intersection(side) && along(o) < along(vehicle);
+            on_ring || ahead
+        })
+    }
+
+    // Local point of right-hand traffic seen from the side driven on, and back
+    fn mirror(&self, (x, y): (i32, i32)) -> (i32, i32) {
+        (x * self.side.sign(), y)
+    }
+    // Local route of right-hand traffic seen from the side driven on
+    fn mirror_route(&self, route: Direction) -> Direction {
+        match (self.side, route) {
+            (DrivingSide::Left, Direction::East) => Direction::West,
+            (DrivingSide::Left, Direction::West) => Direction::East,
+            _ => route,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::demand::APPROACHES;
+    use crate::network::Network;
+    use crate::scenario::TURNS;
+    use crate::world::World;
+
+    #[test]
+    fn exits_on_their_lanes() {
+        let center = Point::new(500, 500);
+        for side in [DrivingSide::Right, DrivingSide::Left] {
+            let ring = Roundabout::new(center, side).with_lanes(2);
+            // the first corner of the ring, on the lane of the exit
+            let corner = |turn| {
+                let position = lane_position(center, Direction::North, turn, side, Point::new(center.x, 0));
+                ring.waypoints(Direction::North, turn, position)[1].point
+            };
+            let (near, far) = (side.mirror(Direction::Right), side.mirror(Direction::Left));
+            assert_eq!((corner(near).y - center.y, corner(Direction::Forward).y - center.y), (-RING_RADIUS, -RING_RADIUS));
+            assert_eq!(corner(far).y - center.y, -ring.lane_radius(1));
+            assert_eq!((corner(far).x - center.x) * side.sign(), -ring.lane_radius(1));
+        }
+        assert_eq!(Roundabout::new(center, DrivingSide::Right).island_radius(), RING_RADIUS - LANE / 2);
+    }
+
+    #[test]
+    fn every_vehicle_goes_round() {
+        for lanes in 1..=MAX_RING_LANES {
+            for side in [DrivingSide::Right, DrivingSide::Left] {
+                let network = Network::default().with_driving_side(side).with_roundabouts(lanes);
+                let mut world = World::new(1).with_network(network);
+                let mut spawned = 0;
+                for _ in 0..3 {
+                    for route in APPROACHES {
+                        for turn in TURNS {
+                            spawned += world.spawn(route, turn).is_some() as usize;
+                        }
+                    }
+                }
+                for _ in 0..20_000 {
+                    world.step();
+                }
+                assert_eq!(world.stats.len(), spawned, "{} lanes driving on the {:?}", lanes, side);
+                assert_eq!(world.stats.iter().map(|s| s.colision).sum::<u32>(), 0);
+            }
+        }
+    }
+}
@@ -0,0 +1,369 @@
+use sdl2::rect::{Point, Rect};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::time::Duration;
+
+use crate::vehicle::Vehicle;
+use crate::network::Network;
+use crate::{Direction, ROAD_NUMBER, ROAD_WIDTH, SCREEN_HEIGHT, SCREEN_WIDTH, TICK};
+
+// Longest time-to-collision considered, beyond it vehicles are not in conflict
+const TTC_HORIZON: f64 = 10.0;
+
+// Thresholds under (or above, for DRAC) which a measure is logged as a conflict
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, Debug, PartialEq, Copy)]
+pub struct SafetyConfig {
+    pub ttc: f64,   // seconds
+    pub pet: f64,   // seconds
+    pub drac: f64,  // px/s², 37 px/s² is about 3.4 m/s² with a 50 px (4.5 m) vehicle
+}
+impl Default for SafetyConfig {
+    fn default() -> Self {
+        SafetyConfig {
+            ttc: 1.5,
+            pet: 1.0,
+            drac: 37.0,
+        }
+    }
+}
+
+// Surrogate safety measure of a conflict
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, Debug, PartialEq, Copy)]
+pub enum Measure {
+    Ttc,    // time-to-collision
+    Pet,    // post-encroachment time
+    Drac,   // deceleration rate to avoid crash
+}
+
+// Conflict crossing a threshold, logged once per vehicle pair and measure
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, Debug, PartialEq, Copy)]
+pub struct SafetyEvent {
+    pub measure: Measure,
+    pub time: Duration,
+    pub vehicles: (u32, u32),
+    pub value: f64,
+    pub x: i32,     // position of the conflict
+    pub y: i32,
+}
+
+// Worst measures of a vehicle pair over the run
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, Debug, PartialEq, Copy)]
+pub struct PairMeasures {
+    pub vehicles: (u32, u32),
+    pub min_ttc: Option<f64>,
+    pub min_pet: Option<f64>,
+    pub max_drac: Option<f64>,
+}
+
+// Conflict point of the intersection, a lane wide square of in_intersect
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, Debug, PartialEq)]
+struct ConflictPoint {
+    #[cfg_attr(feature = "serde", serde(with = "crate::snapshot::rect"))]
+    area: Rect,
+    occupants: Vec<(u32, Lane)>,            // vehicles in the point during the last tick, with their lane when they entered it
+    last: Option<(u32, Lane, Duration)>,    // last vehicle which left the point, its lane and when
+}
+
+// Route and lateral coordinate of the lane of a vehicle
+type Lane = (Direction, i32);
+
+// Computes TTC, PET and DRAC between the pairs of vehicles each tick
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, Debug, PartialEq)]
+pub struct SafetyMonitor {
+    pub config: SafetyConfig,
+    pub events: Vec<SafetyEvent>,
+    pairs: HashMap<u64, PairMeasures>,
+    points: Vec<ConflictPoint>,
+}
+impl SafetyMonitor {
+    pub fn new(network: &Network, config: SafetyConfig) -> Self {
+        // split every intersection in lane wide squares
+        let lane = ROAD_WIDTH / (ROAD_NUMBER * 2);
+        let mut points = Vec::new();
+        for area in network.nodes.iter().map(|n| n.sector.in_intersect) {
+            for i in 0..area.width() / lane {
+                for j in 0..area.height() / lane {
+                    points.push(ConflictPoint {
+                        area: Rect::new(area.x() + (i * lane) as i32, area.y() + (j * lane) as i32, lane, lane),
+                        occupants: Vec::new(),
+                        last: None,
+                    });
+                }
+            }
+        }
+        SafetyMonitor {
+            config,
+            events: Vec::new(),
+            pairs: HashMap::new(),
+            points,
+        }
+    }
+
+    // Measure the conflicts of the vehicles after they moved at time `now`,
+    // TTC and DRAC only between vehicles on the same or neighbouring tiles, farther ones cannot meet under the thresholds
+    pub fn observe(&mut self, vehicles: &[&Vehicle], now: Duration) {
+        let tiles: Vec<(i32, i32)> = vehicles.iter().map(|v| tile_of(v.position)).collect();
+        let mut on_tile: HashMap<(i32, i32), Vec<usize>> = HashMap::new();
+        for (i, &tile) in tiles.iter().enumerate() {
+            on_tile.entry(tile).or_default().push(i);
+        }
+        for (i, a) in vehicles.iter().enumerate() {
+            let (column, row) = tiles[i];
+            for tile in (column - 1..=column + 1).flat_map(|c| (row - 1..=row + 1).map(move |r| (c, r))) {
+                for &j in on_tile.get(&tile).into_iter().flatten().filter(|&&j| j > i) {
+                    let b = vehicles[j];
+                    if let Some((ttc, closing)) = time_to_collision(a, b) {
+                        let position = midpoint(a.position, b.position);
+                        self.record(Measure::Ttc, (a.id(), b.id()), ttc, now, position);
+                        if ttc > 0.0 {
+                            self.record(Measure::Drac, (a.id(), b.id()), closing / (2.0 * ttc), now, position);
+                        }
+                    }
+                }
+            }
+        }
+        self.observe_points(vehicles, now);
+    }
+
+    // Post-encroachment time: time between a vehicle leaving a conflict point and another one entering it,
+    // but for the followers entering it from the lane of the one which left
+    fn observe_points(&mut self, vehicles: &[&Vehicle], now: Duration) {
+        let mut encroachments = Vec::new();
+        for point in self.points.iter_mut() {
+            // a vehicle turning in the point keeps the lane it entered from
+            let inside: Vec<(u32, Lane)> = vehicles
+                .iter()
+                .filter(|v| v.body.has_intersection(point.area))
+                .map(|v| match point.occupants.iter().find(|&&(id, _)| id == v.id()) {
+                    Some(&occupant) => occupant,
+                    None => (v.id(), lane(v)),
+                })
+                .collect();
+            for &(id, lane) in &point.occupants {
+                if !inside.iter().any(|&(other, _)| other == id) {
+                    point.last = Some((id, lane, now));
+                }
+            }
+            for &(id, lane) in &inside {
+                let entering = !point.occupants.iter().any(|&(other, _)| other == id);
+                if let Some((other, other_lane, time)) = point.last {
+                    if entering && other != id && other_lane != lane {
+                        let pet = now.saturating_sub(time).as_secs_f64();
+                        encroachments.push(((other, id), pet, point.area.center()));
+                    }
+                }
+            }
+            point.occupants = inside;
+        }
+        for (pair, pet, position) in encroachments {
+            self.record(Measure::Pet, pair, pet, now, position);
+        }
+    }
+
+    // Keep the worst value of the pair and log the first time it crosses the threshold
+    fn record(&mut self, measure: Measure, vehicles: (u32, u32), value: f64, now: Duration, position: Point) {
+        let vehicles = (vehicles.0.min(vehicles.1), vehicles.0.max(vehicles.1));
+        let key = ((vehicles.0 as u64) << 32) | vehicles.1 as u64;
+        let pair = self.pairs.entry(key).or_insert(PairMeasures {
+            vehicles,
+            min_ttc: None,
+            min_pet: None,
+            max_drac: None,
+        });
+        let (worst, threshold, lower_is_worse) = match measure {
+            Measure::Ttc => (&mut pair.min_ttc, self.config.ttc, true),
+            Measure::Pet => (&mut pair.min_pet, self.config.pet, true),
+            Measure::Drac => (&mut pair.max_drac, self.config.drac, false),
+        };
+        let critical = |v: f64| if lower_is_worse { v < threshold } else { v > threshold };
+        let already_logged = worst.is_some_and(critical);
+        let worse = worst.is_none_or(|w| if lower_is_worse { value < w } else { value > w });
+        if worse {
+            *worst = Some(value);
+        }
+        if critical(value) && !already_logged {
+            self.events.push(SafetyEvent {
+                measure,
+                time: now,
+                vehicles,
+                value,
+                x: position.x,
+                y: position.y,
+            });
+        }
+    }
+
+    // Worst measures of every pair which met
+    pub fn pairs(&self) -> impl Iterator<Item = &PairMeasures> {
+        self.pairs.values()
+    }
+    // Logged conflicts of one measure
+    pub fn count(&self, measure: Measure) -> usize {
+        self.events.iter().filter(|e| e.measure == measure).count()
+    }
+
+    // Export the logged conflicts as CSV
+    pub fn write_csv<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        writeln!(out, "measure,time,vehicle_a,vehicle_b,value,x,y")?;
+        for e in &self.events {
+            writeln!(
+                out,
+                "{:?},{},{},{},{},{},{}",
+                e.measure,
+                e.time.as_secs_f64(),
+                e.vehicles.0,
+                e.vehicles.1,
+                e.value,
+                e.x,
+                e.y
+            )?;
+        }
+        out.flush()
+    }
+}
+
+// Velocity of a vehicle in px per tick along x and y
+fn velocity_vector(vehicle: &Vehicle) -> (f64, f64) {
+    let v = vehicle.velocity as f64;
+    match vehicle.route {
+        Direction::North => (0.0, v),
+        Direction::South => (0.0, -v),
+        Direction::East => (-v, 0.0),
+        Direction::West => (v, 0.0),
+        _ => (0.0, 0.0),
+    }
+}
+
+// Time (seconds) before the bodies of `a` and `b` touch if both keep their velocity,
+// with the closing speed (px/s), None when they never touch within the horizon
+fn time_to_collision(a: &Vehicle, b: &Vehicle) -> Option<(f64, f64)> {
+    let (va, vb) = (velocity_vector(a), velocity_vector(b));
+    let relative = (va.0 - vb.0, va.1 - vb.1);
+    // interval of time (ticks) during which the bodies overlap on one axis
+    let axis = |a_min: i32, a_max: i32, b_min: i32, b_max: i32, speed: f64| -> Option<(f64, f64)> {
+        if speed == 0.0 {
+            return if a_max > b_min && b_max > a_min {
+                Some((f64::NEG_INFINITY, f64::INFINITY))
+            } else {
+                None
+            };
+        }
+        let t1 = (b_min - a_max) as f64 / speed;
+        let t2 = (b_max - a_min) as f64 / speed;
+        Some((t1.min(t2), t1.max(t2)))
+    };
+    let x = axis(a.body.left(), a.body.right(), b.body.left(), b.body.right(), relative.0)?;
+    let y = axis(a.body.top(), a.body.bottom(), b.body.top(), b.body.bottom(), relative.1)?;
+    let enter = x.0.max(y.0);
+    let exit = x.1.min(y.1);
+    // already overlapping is a collision, not a conflict
+    if enter >= exit || enter < 0.0 {
+        return None;
+    }
+    let tick = TICK.as_secs_f64();
+    let ttc = enter * tick;
+    if ttc > TTC_HORIZON {
+        return None;
+    }
+    let closing = (relative.0.powi(2) + relative.1.powi(2)).sqrt() / tick;
+    Some((ttc, closing))
+}
+
+fn lane(vehicle: &Vehicle) -> Lane {
+    let lateral = match vehicle.route {
+        Direction::North | Direction::South => vehicle.position.x,
+        _ => vehicle.position.y,
+    };
+    (vehicle.route, lateral)
+}
+
+// Column and row of the intersection tile under a position
+fn tile_of(position: Point) -> (i32, i32) {
+    (position.x.div_euclid(SCREEN_WIDTH as i32), position.y.div_euclid(SCREEN_HEIGHT as i32))
+}
+
+fn midpoint(a: Point, b: Point) -> Point {
+    Point::new((a.x + b.x) / 2, (a.y + b.y) / 2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Vehicle driving on `route` with its body centred on (x, y)
+    fn placed(id: u32, route: Direction, x: i32, y: i32, velocity: i32) -> Vehicle {
+        let mut vehicle = Vehicle::new(id, route, Direction::Forward, 0, Duration::ZERO);
+        vehicle.position = Point::new(x, y);
+        vehicle.body = match route {
+            Direction::North | Direction::South => Rect::from_center(vehicle.position, 25, 50),
+            _ => Rect::from_center(vehicle.position, 50, 25),
+        };
+        vehicle.velocity = velocity;
+        vehicle
+    }
+
+    #[test]
+    fn head_on_ttc_and_drac() {
+        let mut monitor = SafetyMonitor::new(&Network::default(), SafetyConfig::default());
+        // 250 px between the bodies closing at 10 px per tick
+        let a = placed(1, Direction::North, 500, 100, 5);
+        let b = placed(2, Direction::South, 500, 400, 5);
+        monitor.observe(&[&a, &b], Duration::ZERO);
+        let pair = *monitor.pairs().next().unwrap();
+        assert_eq!(pair.vehicles, (1, 2));
+        assert!((pair.min_ttc.unwrap() - 0.25).abs() < 1e-9);
+        assert!((pair.max_drac.unwrap() - 2000.0).abs() < 1e-6);
+        assert_eq!((monitor.count(Measure::Ttc), monitor.count(Measure::Drac)), (1, 1));
+
+        // a follower at the speed of its leader never closes in
+        let mut monitor = SafetyMonitor::new(&Network::default(), SafetyConfig::default());
+        let c = placed(3, Direction::North, 500, 300, 5);
+        monitor.observe(&[&a, &c], Duration::ZERO);
+        assert_eq!(monitor.pairs().count(), 0);
+    }
+
+    #[test]
+    fn no_ttc_beyond_the_neighbouring_tiles() {
+        let network = Network::corridor(3);
+        let mut monitor = SafetyMonitor::new(&network, SafetyConfig::default());
+        let a = placed(1, Direction::West, 100, 500, 5);
+        let b = placed(2, Direction::East, 1900, 500, 5);
+        monitor.observe(&[&a, &b], Duration::ZERO);
+        assert!(monitor.pairs().next().unwrap().min_ttc.is_some());
+
+        // the same closing speed two tiles apart
+        let mut monitor = SafetyMonitor::new(&network, SafetyConfig::default());
+        let b = placed(2, Direction::East, 2900, 500, 5);
+        monitor.observe(&[&a, &b], Duration::ZERO);
+        assert_eq!(monitor.pairs().count(), 0);
+    }
+
+    #[test]
+    fn pet_between_crossing_lanes() {
+        let network = Network::default();
+        let center = network.nodes[0].sector.in_intersect.center();
+        let mut monitor = SafetyMonitor::new(&network, SafetyConfig::default());
+        let a = placed(1, Direction::North, center.x, center.y, 0);
+        monitor.observe(&[&a], Duration::ZERO);
+        // `a` leaves the box at 1 s, `b` crosses its lane at 1.5 s and its follower `c` at 2 s
+        monitor.observe(&[], Duration::from_secs(1));
+        let b = placed(2, Direction::West, center.x, center.y, 0);
+        monitor.observe(&[&b], Duration::from_millis(1500));
+        monitor.observe(&[], Duration::from_secs(2));
+        let c = placed(3, Direction::West, center.x, center.y, 0);
+        monitor.observe(&[&c], Duration::from_millis(2500));
+
+        let pets: Vec<(u32, u32, f64)> = monitor.pairs().filter_map(|p| p.min_pet.map(|pet| (p.vehicles.0, p.vehicles.1, pet))).collect();
+        assert_eq!(pets, vec![(1, 2, 0.5)]);
+        assert_eq!(monitor.count(Measure::Pet), 1);
+    }
+}
@@ -0,0 +1,253 @@
+use rand::Rng;
+use std::time::Duration;
+
+use crate::demand::{approach_index, APPROACHES};
+use crate::network::Network;
+use crate::platoon::PlatoonConfig;
+use crate::safety::SafetyConfig;
+use crate::Direction;
+
+// Turns a vehicle can take, in the order used by the columns of the OD matrix
+pub const TURNS: [Direction; 3] = [Direction::Left, Direction::Forward, Direction::Right];
+
+// Index of a turn in the OD matrix columns, None for an approach
+pub fn turn_index(direction: Direction) -> Option<usize> {
+    TURNS.iter().position(|&turn| turn == direction)
+}
+
+// Origin-destination matrix of the intersection
+// rows are the approaches (ordered like APPROACHES), columns the turns (ordered like TURNS),
+// every cell is a flow in vehicles per minute
+#[derive(Clone, Debug, PartialEq)]
+pub struct OdMatrix {
+    pub flows: [[f64; 3]; 4],
+}
+impl Default for OdMatrix {
+    // Same flow on every approach and every turn
+    fn default() -> Self {
+        OdMatrix::from_rates([15.0; 4])
+    }
+}
+impl OdMatrix {
+    // Approach rates (vehicles per minute) split equally between the three turns
+    pub fn from_rates(rates: [f64; 4]) -> Self {
+        let mut flows = [[0.0; 3]; 4];
+        for (row, rate) in flows.iter_mut().zip(rates) {
+            *row = [rate / 3.0; 3];
+        }
+        OdMatrix { flows }
+    }
+    // Approach rates (vehicles per minute) with per-approach turning ratios (Left, Forward, Right)
+    pub fn from_turning_ratios(rates: [f64; 4], ratios: [[f64; 3]; 4]) -> Self {
+        let mut flows = [[0.0; 3]; 4];
+        for i in 0..4 {
+            let total: f64 = ratios[i].iter().sum();
+            if total > 0.0 {
+                for j in 0..3 {
+                    flows[i][j] = rates[i] * ratios[i][j] / total;
+                }
+            }
+        }
+        OdMatrix { flows }
+    }
+    // Turning counts measured on the field during `period`
+    pub fn from_counts(counts: [[u32; 3]; 4], period: Duration) -> Self {
+        let minutes = period.as_secs_f64() / 60.0;
+        let mut flows = [[0.0; 3]; 4];
+        if minutes > 0.0 {
+            for i in 0..4 {
+                for j in 0..3 {
+                    flows[i][j] = counts[i][j] as f64 / minutes;
+                }
+            }
+        }
+        OdMatrix { flows }
+    }
+    // Matrix of a text file: a row of three numbers (Left, Forward, Right, split by commas or spaces) per approach
+    // in the order North, South, East, West, vehicles counted during `minutes <n>` (1 when that line is missing),
+    // lines starting with # are comments
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut minutes = 1.0;
+        let mut rows = Vec::new();
+        for line in text.lines().map(str::trim).filter(|l| !l.is_empty() && !l.starts_with('#')) {
+            if let Some(value) = line.strip_prefix("minutes") {
+                minutes = value.trim().parse().map_err(|_| format!("invalid minutes: {}", line))?;
+                continue;
+            }
+            let row: Vec<f64> = line
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|v| !v.is_empty())
+                .map(|v| v.parse().map_err(|_| format!("invalid count: {}", v)))
+                .collect::<Result<_, _>>()?;
+            match <[f64; 3]>::try_from(row) {
+                Ok(row) if row.iter().all(|v| *v >= 0.0) => rows.push(row),
+                _ => return Err(format!("a row needs three counts: {}", line)),
+            }
+        }
+        let flows: [[f64; 3]; 4] = rows.try_into().map_err(|rows: Vec<_>| format!("4 rows needed, {} found", rows.len()))?;
+        if minutes <= 0.0 {
+            return Err("the counting period must be positive".to_string());
+        }
+        Ok(OdMatrix {
+            flows: flows.map(|row| row.map(|count| count / minutes)),
+        })
+    }
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        OdMatrix::parse(&text)
+    }
+    // Flow (vehicles per minute) from `approach` taking `turn`, none when they are not an approach and a turn
+    pub fn flow(&self, approach: Direction, turn: Direction) -> f64 {
+        match (approach_index(approach), turn_index(turn)) {
+            (Some(i), Some(j)) => self.flows[i][j],
+            _ => 0.0,
+        }
+    }
+    // Total flow (vehicles per minute) entering from `approach`
+    pub fn rate(&self, approach: Direction) -> f64 {
+        approach_index(approach).map_or(0.0, |i| self.flows[i].iter().sum())
+    }
+    // Share of the vehicles of `approach` taking each turn (Left, Forward, Right)
+    pub fn turning_ratios(&self, approach: Direction) -> [f64; 3] {
+        let row = approach_index(approach).map_or([0.0; 3], |i| self.flows[i]);
+        let total: f64 = row.iter().sum();
+        if total <= 0.0 {
+            return [1.0 / 3.0; 3];
+        }
+        row.map(|flow| flow / total)
+    }
+    // Draw the turn of a vehicle entering from `approach`
+    pub fn pick_turn<R: Rng + ?Sized>(&self, approach: Direction, rng: &mut R) -> Direction {
+        TURNS[pick_weighted(&self.turning_ratios(approach), rng)]
+    }
+    // Draw an approach proportionally to the approach rates
+    pub fn pick_approach<R: Rng + ?Sized>(&self, rng: &mut R) -> Direction {
+        let rates = APPROACHES.map(|approach| self.rate(approach));
+        APPROACHES[pick_weighted(&rates, rng)]
+    }
+}
+
+// Index drawn proportionally to `weights`, uniform when they are all null
+fn pick_weighted<R: Rng + ?Sized>(weights: &[f64], rng: &mut R) -> usize {
+    let total: f64 = weights.iter().sum();
+    if total <= 0.0 {
+        return rng.gen_range(0..weights.len());
+    }
+    let mut draw = rng.gen::<f64>() * total;
+    for (i, weight) in weights.iter().enumerate() {
+        if draw < *weight {
+            return i;
+        }
+        draw -= weight;
+    }
+    weights.len() - 1
+}
+
+// Configuration of a simulation run
+#[derive(Clone, Debug, PartialEq)]
+pub struct Scenario {
+    pub od: OdMatrix,           // flows used for the turns (and the approaches of generated demand)
+    pub length: Duration,       // length of the auto-spawn loop
+    pub safety: SafetyConfig,   // thresholds of the logged conflicts
+    pub network: Network,       // intersections simulated, a single one by default
+    pub platooning: Option<PlatoonConfig>,  // connected vehicles forming platoons, off by default
+}
+impl Default for Scenario {
+    fn default() -> Self {
+        Scenario {
+            od: OdMatrix::default(),
+            length: Duration::from_secs(60),
+            safety: SafetyConfig::default(),
+            network: Network::default(),
+            platooning: None,
+        }
+    }
+}
+impl Scenario {
+    pub fn new(od: OdMatrix) -> Self {
+        Scenario {
+            od,
+            ..Scenario::default()
+        }
+    }
+    pub fn with_length(mut self, length: Duration) -> Self {
+        self.length = length;
+        self
+    }
+    pub fn with_safety(mut self, safety: SafetyConfig) -> Self {
+        self.safety = safety;
+        self
+    }
+    pub fn with_network(mut self, network: Network) -> Self {
+        self.network = network;
+        self
+    }
+    pub fn with_platooning(mut self, config: PlatoonConfig) -> Self {
+        self.platooning = Some(config);
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn parse_counts() {
+        let od = OdMatrix::parse("# counted at the junction\nminutes 15\n12,40,8\n10 35 15\n5, 20, 5\n\n6,22,4\n").unwrap();
+        assert_eq!(od, OdMatrix::from_counts([[12, 40, 8], [10, 35, 15], [5, 20, 5], [6, 22, 4]], Duration::from_secs(15 * 60)));
+        assert_eq!(OdMatrix::parse("1,2,3\n1,2,3\n1,2,3\n1,2,3").unwrap().flow(Direction::West, Direction::Right), 3.0);
+        assert!(OdMatrix::parse("1,2,3\n1,2,3\n1,2,3").is_err());
+        assert!(OdMatrix::parse("1,2\n1,2,3\n1,2,3\n1,2,3").is_err());
+        assert!(OdMatrix::parse("1,2,x\n1,2,3\n1,2,3\n1,2,3").is_err());
+        assert!(OdMatrix::parse("minutes 0\n1,2,3\n1,2,3\n1,2,3\n1,2,3").is_err());
+    }
+
+    #[test]
+    fn indices() {
+        assert_eq!(TURNS.map(turn_index), [Some(0), Some(1), Some(2)]);
+        assert_eq!(turn_index(Direction::North), None);
+        let od = OdMatrix::default();
+        assert_eq!(od.flow(Direction::Left, Direction::Forward), 0.0);
+        assert_eq!(od.flow(Direction::North, Direction::South), 0.0);
+        assert_eq!(od.rate(Direction::Right), 0.0);
+    }
+
+    #[test]
+    fn drawn_turns_follow_the_matrix() {
+        let od = OdMatrix::from_counts([[12, 40, 8], [0, 0, 0], [0, 30, 0], [6, 22, 4]], Duration::from_secs(60));
+        let mut rng = StdRng::seed_from_u64(2);
+        let draws = 30_000;
+        for approach in APPROACHES {
+            let mut counts = [0; 3];
+            for _ in 0..draws {
+                counts[turn_index(od.pick_turn(approach, &mut rng)).unwrap()] += 1;
+            }
+            // an approach without flow takes every turn alike
+            for (count, ratio) in counts.iter().zip(od.turning_ratios(approach)) {
+                let share = *count as f64 / draws as f64;
+                assert!((share - ratio).abs() < 0.015, "{:?}: {} drawn, {} expected", approach, share, ratio);
+            }
+        }
+        assert_eq!(od.turning_ratios(Direction::East), [0.0, 1.0, 0.0]);
+        let mut counts = [0; 4];
+        for _ in 0..draws {
+            counts[approach_index(od.pick_approach(&mut rng)).unwrap()] += 1;
+        }
+        // 60, 0, 30 and 32 vehicles per minute
+        for (count, rate) in counts.iter().zip([60.0, 0.0, 30.0, 32.0]) {
+            assert!((*count as f64 / draws as f64 - rate / 122.0).abs() < 0.015);
+        }
+    }
+
+    #[test]
+    fn turning_ratios() {
+        let od = OdMatrix::from_turning_ratios([30.0, 0.0, 10.0, 10.0], [[1.0, 2.0, 1.0], [1.0, 1.0, 1.0], [0.0; 3], [0.0, 1.0, 0.0]]);
+        assert_eq!(od.flows[0], [7.5, 15.0, 7.5]);
+        assert_eq!(od.rate(Direction::South), 0.0);
+        assert_eq!(od.rate(Direction::East), 0.0);
+        assert_eq!(od.flow(Direction::West, Direction::Forward), 10.0);
+    }
+}
@@ -1,112 +1,77 @@
 
 use sdl2::rect::{Point, Rect};
+use crate::control::Control;
+use crate::layout::{DrivingSide, Layout, TurnPoint};
+use crate::roundabout::Roundabout;
 use crate::{ROAD_WIDTH,ROAD_NUMBER};
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[derive(Clone, Debug, PartialEq)]
 pub struct Sector {
+    #[cfg_attr(feature = "serde", serde(with = "crate::snapshot::rect"))]
     pub map: Rect,
+    #[cfg_attr(feature = "serde", serde(with = "crate::snapshot::point"))]
+    pub center: Point,
+    #[cfg_attr(feature = "serde", serde(with = "crate::snapshot::rect"))]
     pub entry_intersect: Rect,
+    #[cfg_attr(feature = "serde", serde(with = "crate::snapshot::rect"))]
     pub in_intersect: Rect,
-    pub turn_north: (Rect,Rect),
-    pub turn_south: (Rect,Rect),
-    pub turn_east: (Rect,Rect),
-    pub turn_west: (Rect,Rect),
+    pub layout: Layout,                     // arms and lanes of the intersection
+    pub turns: Vec<TurnPoint>,              // where the turning lanes of the layout turn
+    pub roundabout: Option<Roundabout>,     // ring replacing the crossing, turns follow it instead of turns
+    pub control: Control,                   // rule for entering the box, unused with a roundabout
 }
 impl Sector {
     pub fn new(map: Rect) -> Sector {
-        let entry_intersect = Rect::new(
-            ((map.width() - ROAD_WIDTH )/2-ROAD_WIDTH/4) as i32,
-            ((map.height() - ROAD_WIDTH )/2-ROAD_WIDTH/4)as i32,
-            ROAD_WIDTH+ROAD_WIDTH/2,
-            ROAD_WIDTH+ROAD_WIDTH/2
-        );
-        let in_intersect = Rect::new(
-            ((map.width() - ROAD_WIDTH )/2 + ROAD_WIDTH / (ROAD_NUMBER * 2))as i32,
-            ((map.height() - ROAD_WIDTH )/2  + ROAD_WIDTH / (ROAD_NUMBER * 2))as i32,
-            ROAD_WIDTH - ROAD_WIDTH / ROAD_NUMBER,
-            ROAD_WIDTH - ROAD_WIDTH / ROAD_NUMBER
-        );
-        let cubesize= ROAD_WIDTH/(ROAD_NUMBER*2);
-        let turn_north = (
-            Rect::from_center(
-                Point::new(
-                    ((map.width() - ROAD_WIDTH) /2 + (ROAD_WIDTH/(ROAD_NUMBER*2))/2)as i32 + ((ROAD_WIDTH/(ROAD_NUMBER*2))*2) as i32,
-                    ((map.height() + ROAD_WIDTH) / 2 - (ROAD_WIDTH/(ROAD_NUMBER*2))/2) as i32 - ((ROAD_WIDTH/(ROAD_NUMBER*2))*2) as i32,
-                ),
-                cubesize,
-                cubesize
-            ),
-            Rect::from_center(
-                Point::new(
-                    ((map.width() - ROAD_WIDTH) /2 + (ROAD_WIDTH/(ROAD_NUMBER*2))/2)as i32,
-                    ((map.height() - ROAD_WIDTH) / 2 + (ROAD_WIDTH/(ROAD_NUMBER*2))/2) as i32
-                ),
-                cubesize,
-                cubesize
-            )
-        );
-        let turn_south = (
-            Rect::from_center(
-                Point::new(
-                    ((map.width() + ROAD_WIDTH) / 2 - (ROAD_WIDTH/(ROAD_NUMBER*2))/2)as i32 - ((ROAD_WIDTH/(ROAD_NUMBER*2))*2) as i32,
-                    ((map.height() - ROAD_WIDTH) / 2 + (ROAD_WIDTH/(ROAD_NUMBER*2))/2) as i32 + ((ROAD_WIDTH/(ROAD_NUMBER*2))*2) as i32
-                ),
-                cubesize,
-                cubesize
-            ),
-            Rect::from_center(
-                Point::new(
-                    ((map.width() + ROAD_WIDTH) / 2 - (ROAD_WIDTH/(ROAD_NUMBER*2))/2)as i32, 
-                    ((map.height() + ROAD_WIDTH) / 2 - (ROAD_WIDTH/(ROAD_NUMBER*2))/2) as i32
-                ),
-                cubesize,
-                cubesize
-            ),
-        );
-        let turn_east = (
-            Rect::from_center(
-                Point::new(
-                    ((map.width() - ROAD_WIDTH) /2 + (ROAD_WIDTH/(ROAD_NUMBER*2))/2)as i32 + ((ROAD_WIDTH/(ROAD_NUMBER*2))*2) as i32,
-                    ((map.height() - ROAD_WIDTH) / 2 + (ROAD_WIDTH/(ROAD_NUMBER*2))/2) as i32 + ((ROAD_WIDTH/(ROAD_NUMBER*2))*2) as i32
-                ),
-                cubesize,
-                cubesize
-            ),
-            Rect::from_center(
-                Point::new(
-                    ((map.width() + ROAD_WIDTH) / 2 - (ROAD_WIDTH/(ROAD_NUMBER*2))/2) as i32,
-                    ((map.height() - ROAD_WIDTH) / 2 + (ROAD_WIDTH/(ROAD_NUMBER*2))/2) as i32
-                ),
-                cubesize,
-                cubesize
-            ),
-        );
-        let turn_west = (
-            Rect::from_center(
-                Point::new(
-                    ((map.width() + ROAD_WIDTH) /2 - (ROAD_WIDTH/(ROAD_NUMBER*2))/2)as i32 - ((ROAD_WIDTH/(ROAD_NUMBER*2))*2) as i32,
-                    ((map.height() + ROAD_WIDTH) / 2 - (ROAD_WIDTH/(ROAD_NUMBER*2))/2) as i32 - ((ROAD_WIDTH/(ROAD_NUMBER*2))*2) as i32
-                ),
-                cubesize,
-                cubesize
-            ),
-            Rect::from_center(
-                Point::new(
-                    ((map.width() - ROAD_WIDTH) /2 + (ROAD_WIDTH/(ROAD_NUMBER*2))/2)as i32,
-                    ((map.height() + ROAD_WIDTH) / 2 - (ROAD_WIDTH/(ROAD_NUMBER*2))/2) as i32
-                ),
-                cubesize,
-                cubesize
-            ),
-        );
+        Sector::around(map.center(), map)
+    }
+    // Intersection centred on `center` of a larger map
+    pub fn around(center: Point, map: Rect) -> Sector {
+        let entry_intersect = Rect::from_center(center, ROAD_WIDTH+ROAD_WIDTH/2, ROAD_WIDTH+ROAD_WIDTH/2);
+        let layout = Layout::cross();
         Sector{
             map,
+            center,
             entry_intersect,
-            in_intersect,
-            turn_north,
-            turn_south,
-            turn_east,
-            turn_west,
+            in_intersect: Sector::intersection_box(center, layout.side),
+            turns: layout.turn_points(center),
+            layout,
+            roundabout: None,
+            control: Control::Heuristic,
+        }
+    }
+    // Box of the crossing, mirrored left to right when driving on the left
+    fn intersection_box(center: Point, side: DrivingSide) -> Rect {
+        let size = ROAD_WIDTH - ROAD_WIDTH / ROAD_NUMBER;
+        let near = (ROAD_WIDTH/2) as i32 - (ROAD_WIDTH / (ROAD_NUMBER * 2)) as i32;
+        let corner = match side {
+            DrivingSide::Right => Point::new(center.x - near, center.y - near),
+            DrivingSide::Left => Point::new(center.x + near - size as i32, center.y - near),
+        };
+        Rect::new(corner.x, corner.y, size, size)
+    }
+    // Same intersection with other arms, its turn points derived from them
+    pub fn with_layout(mut self, layout: Layout) -> Sector {
+        layout.check();
+        self.turns = layout.turn_points(self.center);
+        if layout.side != self.layout.side {
+            self.in_intersect = Sector::intersection_box(self.center, layout.side);
         }
+        self.layout = layout;
+        if let Some(lanes) = self.roundabout.as_ref().map(|ring| ring.lanes) {
+            self = self.with_roundabout(lanes);
+        }
+        self
+    }
+    // Same intersection controlled by other rules
+    pub fn with_control(mut self, control: Control) -> Sector {
+        self.control = control;
+        self
+    }
+    // Same intersection with a roundabout of `lanes` circulating lanes, its ring is the intersection box
+    pub fn with_roundabout(mut self, lanes: u32) -> Sector {
+        let ring = Roundabout::new(self.center, self.layout.side).with_lanes(lanes);
+        self.in_intersect = ring.ring();
+        self.roundabout = Some(ring);
+        self
     }
 }
-// );
\ No newline at end of file
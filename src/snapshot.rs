@@ -0,0 +1,96 @@
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter};
+use std::path::Path;
+use std::time::Duration;
+
+use crate::world::World;
+
+// Complete state of a simulation, enough to resume the run from that exact moment
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub world: World,
+    // start and length of the auto-spawn loop, and demand time already generated
+    pub spawn_loop: (Duration, Duration),
+    pub demand_clock: Duration,
+}
+impl Snapshot {
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let out = BufWriter::new(File::create(path)?);
+        serde_json::to_writer(out, self).map_err(io::Error::from)
+    }
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let input = BufReader::new(File::open(path)?);
+        serde_json::from_reader(input).map_err(io::Error::from)
+    }
+}
+
+// sdl2 geometry has no serde support, rectangles are stored as (x, y, width, height)
+pub(crate) mod rect {
+    use sdl2::rect::Rect;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(rect: &Rect, serializer: S) -> Result<S::Ok, S::Error> {
+        (rect.x(), rect.y(), rect.width(), rect.height()).serialize(serializer)
+    }
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Rect, D::Error> {
+        let (x, y, width, height) = <(i32, i32, u32, u32)>::deserialize(deserializer)?;
+        Ok(Rect::new(x, y, width, height))
+    }
+}
+
+// Points are stored as (x, y)
+pub(crate) mod point {
+    use sdl2::rect::Point;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(point: &Point, serializer: S) -> Result<S::Ok, S::Error> {
+        (point.x(), point.y()).serialize(serializer)
+    }
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Point, D::Error> {
+        let (x, y) = <(i32, i32)>::deserialize(deserializer)?;
+        Ok(Point::new(x, y))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::demand::{DemandGenerator, PoissonDemand};
+    use crate::world::stream_seed;
+    use crate::TICK;
+
+    // Run the world with a busy demand drawn like the simulation does, from the seed and the tick
+    fn run(world: &mut World, demand: &mut PoissonDemand, ticks: u32) {
+        for _ in 0..ticks {
+            demand.reseed(stream_seed(world.seed, world.tick as u64));
+            for spawn in demand.generate(world.clock(), world.clock() + TICK) {
+                world.spawn(spawn.route, spawn.direction);
+            }
+            world.step();
+        }
+    }
+
+    #[test]
+    fn restore_continues_the_run() {
+        let mut world = World::new(11);
+        run(&mut world, &mut PoissonDemand::new(40.0), 300);
+        let snapshot = Snapshot {
+            world: world.clone(),
+            spawn_loop: (Duration::ZERO, Duration::from_secs(60)),
+            demand_clock: world.clock(),
+        };
+        let path = std::env::temp_dir().join(format!("smart_road_snapshot_{}.json", std::process::id()));
+        snapshot.save(&path).unwrap();
+        let mut restored = Snapshot::load(&path).unwrap().world;
+        std::fs::remove_file(&path).unwrap();
+
+        // the run which was never saved and the restored one go on the same way
+        run(&mut world, &mut PoissonDemand::new(40.0), 500);
+        run(&mut restored, &mut PoissonDemand::new(40.0), 500);
+        assert!(!world.vehicles.is_empty());
+        assert_eq!(restored.tick, world.tick);
+        assert_eq!(restored.vehicles, world.vehicles);
+        assert_eq!(restored.stats, world.stats);
+    }
+}
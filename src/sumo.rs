@@ -0,0 +1,406 @@
+use sdl2::rect::Rect;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::time::Duration;
+
+use crate::layout::{ArmRoad, DrivingSide};
+use crate::network::{side_of, turned, Network, Road};
+use crate::replay::{Replay, SpawnEvent};
+use crate::scenario::TURNS;
+use crate::trajectory::Trajectories;
+use crate::vehicle::VEHICLE_CLASSES;
+use crate::xml::{attribute, events, XmlEvent};
+use crate::{Direction, TICK, VEHICLE_HEIGHT};
+
+// Scale of the exported positions, a vehicle (50 px) is about 4.5 m long
+pub const PIXELS_PER_METER: f64 = VEHICLE_HEIGHT as f64 / 4.5;
+// Time left to the last vehicles of a route file to cross the network
+const ROUTES_TAIL: Duration = Duration::from_secs(60);
+
+// Road of a SUMO network between two junctions
+struct Edge {
+    from: String,
+    to: String,
+    lanes: u32,     // lanes open to cars
+}
+
+// SUMO network read for the routes: the network, its junctions and edges
+pub struct SumoNet {
+    pub network: Network,
+    positions: HashMap<String, (f64, f64)>,     // junctions, x to the east and y to the south
+    nodes: HashMap<String, usize>,              // intersection of the junctions kept
+    edges: BTreeMap<String, Edge>,              // sorted by id, so the import does not depend on the order of a hash map
+}
+
+// Network of the junctions of a SUMO `.net.xml`
+pub fn load_net(path: &str) -> Result<SumoNet, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    parse_net(&text)
+}
+
+// Junctions where three roads or more meet become intersections snapped on the grid,
+// the edges between them (followed through the junctions of only two roads) links
+// internal edges, and edges without lane for cars, are skipped
+pub fn parse_net(text: &str) -> Result<SumoNet, String> {
+    let mut positions = HashMap::new();
+    let mut edges = BTreeMap::new();
+    // edge being read: id, from, to, lanes
+    let mut current: Option<(String, Edge)> = None;
+    let mut side = DrivingSide::Right;
+    for event in events(text)? {
+        match event {
+            XmlEvent::Start { name, attributes } => match name.as_str() {
+                "net" if attribute(&attributes, "lefthand") == Some("true") => side = DrivingSide::Left,
+                "junction" if attribute(&attributes, "type") != Some("internal") => {
+                    let id = attribute(&attributes, "id");
+                    let x = attribute(&attributes, "x").and_then(|v| v.parse::<f64>().ok());
+                    let y = attribute(&attributes, "y").and_then(|v| v.parse::<f64>().ok());
+                    if let (Some(id), Some(x), Some(y)) = (id, x, y) {
+                        positions.insert(id.to_string(), (x, -y));
+                    }
+                }
+                "edge" if attribute(&attributes, "function").is_none_or(|f| f == "normal") => {
+                    let id = attribute(&attributes, "id");
+                    let from = attribute(&attributes, "from");
+                    let to = attribute(&attributes, "to");
+                    if let (Some(id), Some(from), Some(to)) = (id, from, to) {
+                        current = Some((id.to_string(), Edge { from: from.to_string(), to: to.to_string(), lanes: 0 }));
+                    }
+                }
+                "lane" => {
+                    let cars = attribute(&attributes, "allow").is_none_or(|a| a.contains("passenger") || a == "all")
+                        && !attribute(&attributes, "disallow").is_some_and(|d| d.contains("passenger") || d == "all");
+                    if let (Some((_, edge)), true) = (current.as_mut(), cars) {
+                        edge.lanes += 1;
+                    }
+                }
+                _ => {}
+            },
+            XmlEvent::End { name } if name == "edge" => {
+                if let Some((id, edge)) = current.take().filter(|(_, e)| e.lanes > 0) {
+                    edges.insert(id, edge);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // Neighbours of every junction
+    let mut neighbours: HashMap<&str, HashSet<&str>> = HashMap::new();
+    for edge in edges.values().filter(|e| positions.contains_key(&e.from) && positions.contains_key(&e.to)) {
+        neighbours.entry(&edge.from).or_default().insert(&edge.to);
+        neighbours.entry(&edge.to).or_default().insert(&edge.from);
+    }
+    let mut junctions: Vec<&str> = neighbours.iter().filter(|(_, n)| n.len() >= 3).map(|(&id, _)| id).collect();
+    if junctions.is_empty() {
+        return Err("no junction of three roads in the network".to_string());
+    }
+    junctions.sort_unstable();
+    let index: HashMap<&str, usize> = junctions.iter().enumerate().map(|(i, id)| (*id, i)).collect();
+
+    // Roads from a junction to the next one, through the junctions of two roads
+    let mut roads = Vec::new();
+    for edge in edges.values().filter(|e| index.contains_key(e.from.as_str())) {
+        let (mut previous, mut at, mut lanes) = (edge.from.as_str(), edge.to.as_str(), edge.lanes);
+        let mut steps = 0;
+        while !index.contains_key(at) && steps < edges.len() {
+            let mut next = edges.values().filter(|e| e.from == at && e.to != previous);
+            match (next.next(), next.next()) {
+                (Some(e), None) => {
+                    (previous, at) = (at, e.to.as_str());
+                    lanes = lanes.min(e.lanes);
+                }
+                _ => break,
+            }
+            steps += 1;
+        }
+        if let Some(&to) = index.get(at) {
+            roads.push(Road { from: index[edge.from.as_str()], to, lanes });
+        }
+    }
+    let points: Vec<(f64, f64)> = junctions.iter().map(|id| positions[*id]).collect();
+    let (mut network, placed, dropped) = Network::snap(&points, &roads);
+    if dropped > 0 {
+        eprintln!("SUMO import: {} junctions or roads did not fit on the grid and were dropped", dropped);
+    }
+    // Arms and lanes of every intersection from the edges of its junction
+    for (id, node) in junctions.iter().zip(&placed) {
+        let Some(node) = *node else { continue };
+        let (x, y) = positions[*id];
+        let mut arms = Vec::new();
+        for edge in edges.values() {
+            let (neighbour, lanes_in, lanes_out) = match (edge.from == *id, edge.to == *id) {
+                (true, false) => (&edge.to, 0, edge.lanes),
+                (false, true) => (&edge.from, edge.lanes, 0),
+                _ => continue,
+            };
+            if let Some(&(nx, ny)) = positions.get(neighbour) {
+                arms.push(ArmRoad { route: side_of(nx - x, ny - y), lanes_in, lanes_out });
+            }
+        }
+        network = network.with_roads(node, &arms);
+    }
+    let network = network.with_driving_side(side);
+    let nodes = junctions
+        .iter()
+        .zip(placed)
+        .filter_map(|(id, node)| node.map(|node| (id.to_string(), node)))
+        .collect();
+    Ok(SumoNet { network, positions, nodes, edges })
+}
+
+impl SumoNet {
+    // Route of the vehicles driving along an edge, named after the side they come from
+    fn heading(&self, edge: &Edge) -> Option<Direction> {
+        let ((x0, y0), (x1, y1)) = (*self.positions.get(&edge.from)?, *self.positions.get(&edge.to)?);
+        Some(side_of(x0 - x1, y0 - y1))
+    }
+
+    // Where a vehicle following `edges` enters the network and its turns: (node, route, turn, next turns)
+    // the turns stop at the first intersection the network does not lead to (dropped road, U-turn)
+    fn crossings(&self, edges: &[&str]) -> Option<(usize, Direction, Direction, Vec<Direction>)> {
+        let mut turns: Vec<(usize, Direction, Direction)> = Vec::new();
+        for pair in edges.windows(2) {
+            let (a, b) = (self.edges.get(pair[0])?, self.edges.get(pair[1])?);
+            let Some(&node) = self.nodes.get(&a.to) else { continue };
+            let (into, out) = (self.heading(a)?, self.heading(b)?);
+            let Some(turn) = TURNS.into_iter().find(|&t| turned(into, t) == out) else { break };
+            // no longer path than a drawn one, as a run log is checked against the network
+            if turns.len() > self.network.nodes.len() * 2 {
+                break;
+            }
+            if let Some(&(last, route, last_turn)) = turns.last() {
+                if self.network.next(last, turned(route, last_turn)) != Some(node) || turned(route, last_turn) != into {
+                    break;
+                }
+            }
+            turns.push((node, into, turn));
+        }
+        let &(node, route, turn) = turns.first()?;
+        Some((node, route, turn, turns[1..].iter().map(|t| t.2).collect()))
+    }
+
+    // Run log playing back the vehicles of a SUMO `.rou.xml`
+    pub fn load_routes(&self, path: &str) -> Result<Replay, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        self.parse_routes(&text)
+    }
+
+    // Vehicles and flows with a route (named or nested) become spawns at their departure time,
+    // trips (without route), vehicles departing on a trigger (not a time) and vehicles not crossing an intersection
+    // of the network are skipped
+    pub fn parse_routes(&self, text: &str) -> Result<Replay, String> {
+        let mut named: HashMap<String, String> = HashMap::new();
+        // vehicles found: departure times and route (edges or name)
+        let mut demands: Vec<(Vec<f64>, Option<String>)> = Vec::new();
+        let mut inside = false;     // in a vehicle or a flow, for nested routes
+        let mut skipped = 0;
+        for event in events(text)? {
+            match event {
+                XmlEvent::Start { name, attributes } => match name.as_str() {
+                    "route" => {
+                        let edges = attribute(&attributes, "edges").unwrap_or_default().to_string();
+                        match (inside, attribute(&attributes, "id")) {
+                            (true, _) => {
+                                if let Some(demand) = demands.last_mut() {
+                                    demand.1 = Some(edges);
+                                }
+                            }
+                            (false, Some(id)) => {
+                                named.insert(id.to_string(), edges);
+                            }
+                            _ => {}
+                        }
+                    }
+                    "vehicle" | "flow" => {
+                        inside = true;
+                        let route = attribute(&attributes, "route").and_then(|r| named.get(r)).cloned();
+                        let departs = if name == "vehicle" {
+                            let depart = attribute(&attributes, "depart").and_then(|d| d.parse().ok());
+                            if depart.is_none() {
+                                skipped += 1;
+                            }
+                            depart.into_iter().collect()
+                        } else {
+                            flow_departs(&attributes)
+                        };
+                        demands.push((departs, route));
+                    }
+                    "trip" => skipped += 1,
+                    _ => {}
+                },
+                XmlEvent::End { name } if name == "vehicle" || name == "flow" => inside = false,
+                _ => {}
+            }
+        }
+
+        let mut spawns = Vec::new();
+        for (departs, route) in demands {
+            let edges: Vec<&str> = route.as_deref().unwrap_or_default().split_whitespace().collect();
+            let Some((node, route, direction, path)) = self.crossings(&edges) else {
+                skipped += departs.len();
+                continue;
+            };
+            for depart in departs {
+                spawns.push(SpawnEvent {
+                    tick: (depart / TICK.as_secs_f64()).round() as u32,
+                    route,
+                    direction,
+                    class: (spawns.len() % VEHICLE_CLASSES.len()) as u8,
+                    node: node as u16,
+                    path: path.clone(),
+                });
+            }
+        }
+        if skipped > 0 {
+            eprintln!("SUMO import: {} vehicles without a departure time or a route through the network were skipped", skipped);
+        }
+        spawns.sort_by_key(|s| s.tick);
+        let end = spawns.last().map_or(0, |s| s.tick) + (ROUTES_TAIL.as_millis() / TICK.as_millis()) as u32;
+        Ok(Replay {
+            seed: 0,
+            spawns,
+            frames: Vec::new(),
+            end,
+        })
+    }
+}
+
+// Departure times (s) of a flow: evenly spaced between begin and end
+fn flow_departs(attributes: &[(String, String)]) -> Vec<f64> {
+    let number = |key: &str| attribute(attributes, key).and_then(|v| v.parse::<f64>().ok());
+    let begin = number("begin").unwrap_or(0.0);
+    let end = number("end").unwrap_or(3600.0);
+    let period = match (number("period"), number("vehsPerHour"), number("number"), number("probability")) {
+        (Some(period), ..) => period,
+        (_, Some(rate), ..) => 3600.0 / rate,
+        (_, _, Some(count), _) => (end - begin) / count,
+        // one vehicle every 1/p seconds on average
+        (_, _, _, Some(probability)) => 1.0 / probability,
+        _ => return Vec::new(),
+    };
+    if period <= 0.0 || !period.is_finite() {
+        return Vec::new();
+    }
+    let mut departs = Vec::new();
+    let mut depart = begin;
+    while depart < end {
+        departs.push(depart);
+        depart += period;
+    }
+    departs
+}
+
+// Trajectories in the floating car data format of SUMO (`--fcd-output`), positions in metres with y to the north
+pub fn write_fcd(trajectories: &Trajectories, map: Rect, path: &str) -> io::Result<()> {
+    let mut steps: BTreeMap<Duration, Vec<String>> = BTreeMap::new();
+    for trajectory in trajectories.list() {
+        let mut angle = 0.0;
+        for (i, point) in trajectory.points.iter().enumerate() {
+            let mut speed = 0.0;
+            if let Some(before) = i.checked_sub(1).map(|i| trajectory.points[i]) {
+                let (dx, dy) = (point.position.x - before.position.x, point.position.y - before.position.y);
+                if dx != 0 || dy != 0 {
+                    // navigation angle: clockwise from the north
+                    angle = (dx as f64).atan2(-dy as f64).to_degrees().rem_euclid(360.0);
+                }
+                let time = (point.time - before.time).as_secs_f64();
+                if time > 0.0 {
+                    speed = (point.distance - before.distance) as f64 / time / PIXELS_PER_METER;
+                }
+            }
+            steps.entry(point.time).or_default().push(format!(
+                "        <vehicle id=\"{}\" x=\"{:.2}\" y=\"{:.2}\" angle=\"{:.2}\" type=\"DEFAULT_VEHTYPE\" speed=\"{:.2}\" pos=\"{:.2}\" slope=\"0.00\"/>",
+                trajectory.id,
+                (point.position.x - map.x()) as f64 / PIXELS_PER_METER,
+                (map.bottom() - point.position.y) as f64 / PIXELS_PER_METER,
+                angle,
+                speed,
+                point.distance as f64 / PIXELS_PER_METER,
+            ));
+        }
+    }
+    let mut out = BufWriter::new(File::create(path)?);
+    writeln!(out, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>")?;
+    writeln!(out, "<fcd-export>")?;
+    for (time, vehicles) in steps {
+        writeln!(out, "    <timestep time=\"{:.2}\">", time.as_secs_f64())?;
+        for vehicle in vehicles {
+            writeln!(out, "{}", vehicle)?;
+        }
+        writeln!(out, "    </timestep>")?;
+    }
+    writeln!(out, "</fcd-export>")?;
+    out.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A junction C with dead ends to the north (2 lanes toward C), east and south, and no road to the west
+    const NET: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<net version="1.9">
+    <edge id=":C_0" function="internal"><lane id=":C_0_0" index="0" length="5"/></edge>
+    <edge id="NC" from="N" to="C"><lane id="NC_0" index="0"/><lane id="NC_1" index="1"/></edge>
+    <edge id="CN" from="C" to="N"><lane id="CN_0" index="0"/></edge>
+    <edge id="EC" from="E" to="C"><lane id="EC_0" index="0" allow="pedestrian"/><lane id="EC_1" index="1"/></edge>
+    <edge id="CE" from="C" to="E"><lane id="CE_0" index="0"/></edge>
+    <edge id="SC" from="S" to="C"><lane id="SC_0" index="0"/></edge>
+    <edge id="CS" from="C" to="S"><lane id="CS_0" index="0"/></edge>
+    <junction id="C" type="priority" x="0.00" y="0.00"/>
+    <junction id="N" type="dead_end" x="0.00" y="100.00"/>
+    <junction id="E" type="dead_end" x="100.00" y="0.00"/>
+    <junction id="S" type="dead_end" x="0.00" y="-100.00"/>
+    <junction id=":C_0_0" type="internal" x="0.00" y="0.00"/>
+</net>"#;
+
+    const ROUTES: &str = r#"<routes>
+    <route id="r" edges="NC CE"/>
+    <vehicle id="v0" depart="1.5" route="r"/>
+    <vehicle id="v1" depart="triggered" route="r"/>
+    <flow id="f" begin="0" end="10" period="5"><route edges="SC CN"/></flow>
+    <trip id="t" depart="0" from="NC" to="CE"/>
+</routes>"#;
+
+    #[test]
+    fn net_layout() {
+        let net = parse_net(NET).unwrap();
+        assert_eq!(net.network.nodes.len(), 1);
+        let layout = &net.network.nodes[0].sector.layout;
+        assert!(layout.arm(Direction::West).is_none());
+        // two lanes toward C, but a single lane in front to go straight on
+        let north = layout.arm(Direction::North).unwrap();
+        assert_eq!(north.lanes, vec![vec![Direction::Left, Direction::Forward]]);
+        assert_eq!(north.exits, 1);
+        // the pedestrian lane is not counted
+        assert_eq!(layout.arm(Direction::East).unwrap().lanes.len(), 1);
+        // the same file gives the same network
+        assert_eq!(parse_net(NET).unwrap().network, net.network);
+    }
+
+    #[test]
+    fn routes() {
+        let net = parse_net(NET).unwrap();
+        let replay = net.parse_routes(ROUTES).unwrap();
+        let spawns: Vec<(u32, Direction, Direction, u16)> =
+            replay.spawns.iter().map(|s| (s.tick, s.route, s.direction, s.node)).collect();
+        assert_eq!(
+            spawns,
+            vec![
+                (0, Direction::South, Direction::Forward, 0),
+                (150, Direction::North, Direction::Left, 0),
+                (500, Direction::South, Direction::Forward, 0),
+            ]
+        );
+        assert!(replay.spawns.iter().all(|s| s.path.is_empty()));
+        assert_eq!(replay.end, 500 + (ROUTES_TAIL.as_millis() / TICK.as_millis()) as u32);
+    }
+
+    #[test]
+    fn lefthand() {
+        let net = parse_net(&NET.replace("<net version=\"1.9\">", "<net version=\"1.9\" lefthand=\"true\">")).unwrap();
+        assert_eq!(net.network.nodes[0].sector.layout.side, DrivingSide::Left);
+    }
+}
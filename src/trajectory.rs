@@ -0,0 +1,124 @@
+use sdl2::rect::Point;
+use std::collections::HashMap;
+use std::time::Duration;
+
+use crate::world::World;
+use crate::{Direction, TICK};
+
+// Ticks between two recorded positions of a vehicle
+pub const TRAJECTORY_TICKS: u32 = 10;
+// Age of the oldest position drawn in a trail
+pub const TRAIL_LENGTH: Duration = Duration::from_secs(2);
+
+// Recorded position of a vehicle
+#[derive(Clone, Debug, PartialEq, Copy)]
+pub struct TrajectoryPoint {
+    pub time: Duration,
+    pub position: Point,
+    pub distance: u32,  // travelled along the route since the vehicle entered the map
+}
+
+// Positions of one vehicle over its run
+#[derive(Clone, Debug, PartialEq)]
+pub struct Trajectory {
+    pub id: u32,
+    pub approach: Direction,    // route the vehicle entered from
+    pub lane: Direction,        // turn of the lane it entered on
+    pub points: Vec<TrajectoryPoint>,
+}
+
+// Trajectories of every vehicle seen, recorded from the world after each update
+// kept outside the world so rewinding does not copy them, they are cut back instead
+#[derive(Clone, Debug, Default)]
+pub struct Trajectories {
+    list: Vec<Trajectory>,
+    index: HashMap<u32, usize>,     // position in `list` of a vehicle id
+    last: Option<Duration>,         // time of the last recorded positions
+}
+impl Trajectories {
+    pub fn new() -> Self {
+        Trajectories::default()
+    }
+
+    // Record the positions of the vehicles every TRAJECTORY_TICKS,
+    // the positions after the world clock are dropped first when it went back
+    pub fn record(&mut self, world: &World) {
+        let now = world.clock();
+        if self.last.is_some_and(|last| now < last) {
+            self.truncate(now);
+        }
+        if self.last.is_some_and(|last| now < last + TICK * TRAJECTORY_TICKS) {
+            return;
+        }
+        for vehicle in &world.vehicles {
+            let i = match self.index.get(&vehicle.id()) {
+                Some(&i) => i,
+                None => {
+                    self.list.push(Trajectory {
+                        id: vehicle.id(),
+                        approach: vehicle.route,
+                        lane: vehicle.direction,
+                        points: Vec::new(),
+                    });
+                    self.index.insert(vehicle.id(), self.list.len() - 1);
+                    self.list.len() - 1
+                }
+            };
+            let points = &mut self.list[i].points;
+            let distance = match points.last() {
+                Some(last) => {
+                    last.distance
+                        + (vehicle.position.x - last.position.x).unsigned_abs()
+                        + (vehicle.position.y - last.position.y).unsigned_abs()
+                }
+                None => 0,
+            };
+            points.push(TrajectoryPoint {
+                time: now,
+                position: vehicle.position,
+                distance,
+            });
+        }
+        self.last = Some(now);
+    }
+
+    // Drop the positions recorded after `time`
+    fn truncate(&mut self, time: Duration) {
+        for trajectory in self.list.iter_mut() {
+            trajectory.points.retain(|p| p.time <= time);
+        }
+        self.list.retain(|t| !t.points.is_empty());
+        self.index = self.list.iter().enumerate().map(|(i, t)| (t.id, i)).collect();
+        self.last = self.list.iter().filter_map(|t| t.points.last()).map(|p| p.time).max();
+    }
+
+    // Every trajectory recorded, in the order the vehicles were first seen
+    pub fn list(&self) -> &[Trajectory] {
+        &self.list
+    }
+    // Positions of a vehicle over the last TRAIL_LENGTH, oldest first
+    pub fn trail(&self, id: u32) -> Vec<Point> {
+        let Some(&i) = self.index.get(&id) else { return Vec::new() };
+        let points = &self.list[i].points;
+        let Some(last) = points.last() else { return Vec::new() };
+        points
+            .iter()
+            .filter(|p| last.time.saturating_sub(p.time) <= TRAIL_LENGTH)
+            .map(|p| p.position)
+            .collect()
+    }
+
+    // Time-space diagram of a lane: for each vehicle, (time (s), distance (px)) points
+    pub fn time_space(&self, approach: Direction, lane: Direction) -> Vec<Vec<(f64, f64)>> {
+        self.list
+            .iter()
+            .filter(|t| t.approach == approach && t.lane == lane)
+            .map(|t| {
+                t.points
+                    .iter()
+                    .map(|p| (p.time.as_secs_f64(), p.distance as f64))
+                    .collect()
+            })
+            .collect()
+    }
+}
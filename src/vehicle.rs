@@ -1,12 +1,16 @@
-use rand::Rng;
-use sdl2::libc::dirent;
 use sdl2::rect::{Point, Rect};
 use std::collections::VecDeque;
-use std::time::{Instant,Duration};
+use std::time::Duration;
 
-use crate::{ROAD_NUMBER, ROAD_WIDTH, SAFE_DISTANCE, SCREEN_HEIGHT, SCREEN_WIDTH, VEHICLE_HEIGHT, VEHICLE_WIDTH};
+use crate::{ROAD_NUMBER, ROAD_WIDTH, SAFE_DISTANCE, SCREEN_HEIGHT, SCREEN_WIDTH, TICK, VEHICLE_HEIGHT, VEHICLE_WIDTH};
 use crate::{Direction, Velocity, Sector};
+use crate::replay::VehicleState;
+use crate::control::{at_line, Control};
+use crate::layout::DrivingSide;
+use crate::platoon;
+use crate::roundabout::Waypoint;
 
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[derive(Clone, Debug, PartialEq,Copy)]
 pub struct Stats{
     pub velocity: u32,  // Calculate after arrival
@@ -14,134 +18,229 @@ pub struct Stats{
     pub time: Duration, // increments until arrival
     pub close_call:u32,
     pub colision:u32,
+    pub queue_delay: Duration,  // time waited in the entry queue before spawning
+    pub approach: Direction,    // route the vehicle entered from
+    pub turn: Direction,        // direction the vehicle took at the intersection
+    pub arrival: Duration,      // simulation time the vehicle left the map
+    pub stops: u32,             // times the vehicle came to a standstill
+    pub stopped_time: Duration, // time spent at a standstill
+    pub control_delay: Duration,    // queue delay plus travel time above free flow, calculate after arrival
+    pub box_distance: u32,      // part of the distance driven with the body in entry_intersect, where free flow is Normal
 }
 
 
+// Rule which stopped a vehicle, with the id of the vehicle responsible
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, Debug, PartialEq,Copy)]
+pub enum StopReason{
+    Collision(u32),         // body touches another body
+    IntersectionFull,       // too many vehicles (or turning vehicles) in the intersection
+    VehicleAhead(u32),      // urgency_stop hitbox touches another body
+    LeftPriority(u32),      // left hitbox touches a vehicle coming from the left
+    RightPriority(u32),     // right hitbox touches a vehicle while turning
+    GiveWay,                // waiting to enter a roundabout
+    StopLine,               // mandatory stop at the line of a stop sign
+    RightOfWay(u32),        // waiting for a vehicle with the right of way under the control of the intersection
+    Platoon(u32),           // letting the admitted platoon of a leader cross
+}
+impl std::fmt::Display for StopReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            StopReason::Collision(id) => write!(f, "collision with #{}", id),
+            StopReason::IntersectionFull => write!(f, "intersection full"),
+            StopReason::VehicleAhead(id) => write!(f, "vehicle #{} ahead", id),
+            StopReason::LeftPriority(id) => write!(f, "yield to #{} on the left", id),
+            StopReason::RightPriority(id) => write!(f, "yield to #{} on the right", id),
+            StopReason::GiveWay => write!(f, "give way to the roundabout"),
+            StopReason::StopLine => write!(f, "stop at the line"),
+            StopReason::RightOfWay(id) => write!(f, "right of way to #{}", id),
+            StopReason::Platoon(id) => write!(f, "platoon of #{} crossing", id),
+        }
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[derive(Clone, Debug, PartialEq,Copy)]
 pub struct Hitbox{
+    #[cfg_attr(feature = "serde", serde(with = "crate::snapshot::rect"))]
     pub urgency_stop: Rect, // Front stop hitbox
+    #[cfg_attr(feature = "serde", serde(with = "crate::snapshot::rect"))]
     pub closer: Rect,
+    #[cfg_attr(feature = "serde", serde(with = "crate::snapshot::rect"))]
     pub slowdown_1: Rect,   // Hitbox to slow Fast to Normal
+    #[cfg_attr(feature = "serde", serde(with = "crate::snapshot::rect"))]
     pub slowdown_2: Rect,   // Hitbox to slow Normal to Slow
+    #[cfg_attr(feature = "serde", serde(with = "crate::snapshot::rect"))]
     pub left: Rect,         // Left hitbox
+    #[cfg_attr(feature = "serde", serde(with = "crate::snapshot::rect"))]
     pub right: Rect,        // Right hitbox
 }
 // Struct for vehicles, which includes position, direction of movement, route, and color
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[derive(Clone, Debug, PartialEq)]
 pub struct Vehicle {
     // Element
-    id: Instant,                    // id store an id, unique for each vehicle
-    pub texture: String,            // path to vehicle texture (png)
+    id: u32,                        // id store an id, unique for each vehicle
+    pub class: usize,               // index of the vehicle colour in VEHICLE_CLASSES
+    #[cfg_attr(feature = "serde", serde(with = "crate::snapshot::point"))]
     pub position:Point,             // x and y position of the center of vehicle
     pub direction: Direction,       // final destination direction
     pub route: Direction,           // actual route
+    #[cfg_attr(feature = "serde", serde(with = "crate::snapshot::rect"))]
     pub body: Rect,                 // global postion used by vehicle
     // Detection
     pub hitbox:Hitbox,  // all hitbox for obstacles detection
     // Movement
     pub velocity: i32,                  // velocity of vehicle
     pub speed: Velocity,                // actual speed objectiv
-    pub last_acceleration: Instant,     // last acceleration
+    pub spawn_time: Duration,           // simulation time the vehicle entered the map
+    pub last_acceleration: Duration,    // simulation time of the last acceleration
     pub acceleration_delay: Duration,   // acceleration
     // Stats
     pub stats: Stats,           // stats of vehicle
     // Check
     close: (bool,bool),    // limit the the close call
+    pub stop_reason: Option<StopReason>,    // why the last detection asked to stop
+    // Network
+    pub node: usize,                // intersection the vehicle is driving through
+    pub path: VecDeque<Direction>,  // turns to take at the next intersections
+    pub waypoints: VecDeque<Waypoint>,  // turns around the roundabout being crossed
+    pub side: DrivingSide,              // side of the road the vehicle drives on
+    pub stopped_at: Option<Duration>,   // when the vehicle stopped at the line of the intersection it drives through
+    pub platoon: Option<u32>,           // id of the leader of the platoon the vehicle drives in
+    pub entered: bool,                  // the vehicle entered the box of the intersection it drives through
 
 }
 const SPEED_V:(u32,u32,u32) = (1,3,5);
+// Vehicle colours, each one has its sprite in assets/
+pub const VEHICLE_CLASSES: [&str; 7] = ["red", "blue", "green", "yellow", "orange", "black", "white"];
 
 impl Vehicle {
-    pub fn new(route: Direction) -> Self {
-
-        // Random direction
-        let directions = [Direction::Left, Direction::Right, Direction::Forward];
-        let random_direction = directions[rand::thread_rng().gen_range(0..directions.len())];
+    pub fn new(id: u32, route: Direction, direction: Direction, class: usize, now: Duration) -> Self {
 
-        // Displacement based on random direction
-        let displacement = match random_direction {
-            Direction::Left => (ROAD_WIDTH / (ROAD_NUMBER * 2)) as i32 * 2,
-            Direction::Forward => (ROAD_WIDTH / (ROAD_NUMBER * 2)) as i32,
-            Direction::Right | _ => 0,
-        };
-
-        // Calculate common values
-        let half_vehicle_height = VEHICLE_HEIGHT as i32 / 2;
-        // let half_vehicle_width = VEHICLE_WIDTH as i32 / 2;
-
-        // Position
-        let position= match route {
-            Direction::North => {
-                Point::new(
-                    ((SCREEN_WIDTH - ROAD_WIDTH) / 2 + ROAD_WIDTH / (ROAD_NUMBER * 2) / 2) as i32 + displacement,
-                    -half_vehicle_height,
-                    // half_vehicle_height,
-                )
-            }
-            Direction::South => {
-                Point::new(
-                    ((SCREEN_WIDTH + ROAD_WIDTH) / 2 - ROAD_WIDTH / (ROAD_NUMBER * 2) / 2) as i32 - displacement,
-                    SCREEN_HEIGHT as i32 + half_vehicle_height,
-                )
-            }
-            Direction::East => {
-                Point::new(
-                    SCREEN_WIDTH as i32 + half_vehicle_height,
-                    ((SCREEN_HEIGHT - ROAD_WIDTH) / 2 + ROAD_WIDTH / (ROAD_NUMBER * 2) / 2) as i32 + displacement,
-                )
-            }
-            Direction::West => {
-                Point::new(
-                    -half_vehicle_height,
-                    ((SCREEN_HEIGHT + ROAD_WIDTH) / 2 - ROAD_WIDTH / (ROAD_NUMBER * 2) / 2) as i32 - displacement,
-                )
-            }
-            _ => todo!(),
-        };
+        // Position at the edge of the screen, on the lane of the turn
+        let position = entry_position(Rect::new(0, 0, SCREEN_WIDTH, SCREEN_HEIGHT), route, default_lane(direction), DrivingSide::Right);
 
         // calculate body and hitboxes
-        let (body,hitbox) = cal_hitboxes(position,route);
-
-        // Set random image path
-        let images = ["red", "blue", "green", "yellow", "orange", "black", "white"];
-        let image_path = format!("assets/{}.png", images[rand::thread_rng().gen_range(0..images.len())]);
+        let (body,hitbox) = side_hitboxes(position, route, DrivingSide::Right);
 
         // Create the vehicle
         Self {
-            id : Instant::now(),
-            texture: image_path,
+            id,
+            class,
             position,
             body,
             hitbox,
-            direction: random_direction,
+            direction,
             route,
             velocity: SPEED_V.2 as i32,
             speed: Velocity::Fast,
             acceleration_delay: Duration::from_millis(150),
-            last_acceleration: Instant::now(),
+            spawn_time: now,
+            last_acceleration: now,
             stats: Stats {
                 velocity: 0,
                 time: Duration::from_secs(0),
                 distance: 0,
                 close_call: 0,
                 colision: 0,
+                queue_delay: Duration::from_secs(0),
+                approach: route,
+                turn: direction,
+                arrival: Duration::from_secs(0),
+                stops: 0,
+                stopped_time: Duration::from_secs(0),
+                control_delay: Duration::from_secs(0),
+                box_distance: 0,
             },
             close: (false,false),
+            stop_reason: None,
+            node: 0,
+            path: VecDeque::new(),
+            waypoints: VecDeque::new(),
+            side: DrivingSide::Right,
+            stopped_at: None,
+            platoon: None,
+            entered: false,
         }
     }
+    // Drive on the other side of the road, before entering the network
+    pub fn with_side(mut self, side: DrivingSide) -> Self {
+        self.side = side;
+        (self.body, self.hitbox) = side_hitboxes(self.position, self.route, side);
+        self
+    }
+    // Enter on lane `lane` at the edge of the tile of an intersection of a network,
+    // then take the turns of `path` at the next intersections
+    pub fn with_path(mut self, node: usize, tile: Rect, lane: usize, path: Vec<Direction>) -> Self {
+        self.node = node;
+        self.path = path.into();
+        self.position = entry_position(tile, self.route, lane, self.side);
+        (self.body, self.hitbox) = side_hitboxes(self.position, self.route, self.side);
+        self
+    }
+    // Drive on to the next intersection, centred on `center`, in lane `lane` for the turn to take there
+    pub fn enter_node(&mut self, node: usize, center: Point, direction: Direction, lane: usize) {
+        self.node = node;
+        self.stopped_at = None;
+        self.platoon = None;
+        self.entered = false;
+        self.direction = direction;
+        self.position = lane_point(center, self.route, lane, self.side, self.position);
+        (self.body, self.hitbox) = side_hitboxes(self.position, self.route, self.side);
+    }
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+    // Body currently touching another body
+    pub fn in_collision(&self) -> bool {
+        self.close.1
+    }
+    // Closer hitbox currently touching another body
+    pub fn in_close_call(&self) -> bool {
+        self.close.0
+    }
     // ToDo: optimizing reaction detection
     pub fn is_overlapping(&mut self,sector:Sector, vehicles: VecDeque<Vehicle>) -> u8 {
+    self.stop_reason = None;
     let mut nbt_av_intersects = 0;
     let mut nb_av_intersects = 0;
+    let others: Vec<&Vehicle> = vehicles.iter().collect();
+    // Roundabout: give way at the entry, the priority rules of the crossing do not apply
+    if let Some(ring) = &sector.roundabout {
+        if ring.must_yield(self, &others) {
+            self.stop_reason = Some(StopReason::GiveWay);
+            return 1;
+        }
+    }
+    // Platoons: a follower enters the box behind its admitted leader, the others let the admitted platoons cross
+    let admitted = platoon::admitted(self, &others, &sector);
+    let platoon_size = match self.platoon {
+        Some(leader) if leader == self.id => platoon::size(leader, &others),
+        _ => 1,
+    };
+    // Rule-based control: wait at the line while the rules say so, the priority rules of the crossing do not apply
+    if sector.roundabout.is_none() && !admitted {
+        let wait = platoon::must_yield(self, &others, &sector).or_else(|| sector.control.must_wait(self, &others, &sector));
+        if let Some(reason) = wait {
+            self.stop_reason = Some(reason);
+            return 1;
+        }
+    }
+    let crossing = sector.roundabout.is_none() && sector.control == Control::Heuristic && !admitted;
+    // the rules are written for right-hand traffic, mirrored when driving on the left
+    let side = sector.layout.side;
+    let crossing_turn = side.crossing_turn();
+    let (centre_side, kerb_side) = match side {
+        DrivingSide::Right => (self.hitbox.left, self.hitbox.right),
+        DrivingSide::Left => (self.hitbox.right, self.hitbox.left),
+    };
     for vehicle in vehicles.clone().iter_mut() {
         if vehicle.body.intersection(sector.in_intersect).is_some()
         // && vehicle.id < self.id
         {
-            if vehicle.body.intersection(sector.turn_east.0).is_some()
-            || vehicle.body.intersection(sector.turn_west.0).is_some()
-            || vehicle.body.intersection(sector.turn_north.0).is_some()
-            || vehicle.body.intersection(sector.turn_south.0).is_some()
-            || vehicle.direction == Direction::Left{
+            if sector.turns.iter().any(|t| t.turn == crossing_turn && vehicle.body.intersection(t.area).is_some())
+            || vehicle.direction == crossing_turn{
                 nbt_av_intersects += 1;
                 nb_av_intersects += 1;
             }else{
@@ -167,15 +266,18 @@ impl Vehicle {
                 self.stats.colision += 1;
                 self.close.1 = true;
             }
+            self.stop_reason = Some(StopReason::Collision(vehicle.id));
             return 1;
         }else{
             self.close.1 = false;
         }
 
           // lock the number of vehicle in the intersection
-            if self.hitbox.closer.intersection(sector.in_intersect).is_some()
+            if crossing
+            && self.hitbox.closer.intersection(sector.in_intersect).is_some()
             && !self.body.intersection(sector.in_intersect).is_some()
-            && (nb_av_intersects >= 5 || nbt_av_intersects >= 2){
+            && (nb_av_intersects + platoon_size > 5 || nbt_av_intersects >= 2){
+                self.stop_reason = Some(StopReason::IntersectionFull);
                 return 1
             }
        
@@ -183,26 +285,30 @@ impl Vehicle {
         if self.hitbox.urgency_stop.intersection(vehicle.body).is_some()
         // Av turn right don't take in charge the other ways
         {
+            self.stop_reason = Some(StopReason::VehicleAhead(vehicle.id));
             return 1;
         }
             
-            // Check priority based on direction (Left-hand priority)
-            if self.hitbox.left.intersection(vehicle.hitbox.urgency_stop).is_some() 
+            // Check priority based on direction (Left-hand priority, right-hand one when driving on the left)
+            if crossing
+            && centre_side.intersection(vehicle.hitbox.urgency_stop).is_some() 
             && !self.body.intersection(vehicle.hitbox.urgency_stop).is_some()
             && vehicle.speed != Velocity::Stop
             && self.route != vehicle.route
-            && self.direction != Direction::Right
+            && self.direction != side.kerb_turn()
             && !(
                 (self.route == Direction::North && vehicle.route == Direction::South)
                 || (self.route == Direction::South && vehicle.route == Direction::North)
                 || (self.route == Direction::East && vehicle.route == Direction::West)
                 || (self.route == Direction::West && vehicle.route == Direction::East)
             ){
+                self.stop_reason = Some(StopReason::LeftPriority(vehicle.id));
                 return 1;
             }
             
             // let right-hand priority if vehicle turns left and doesn't have place to turn
-        if self.hitbox.right.intersection(vehicle.body).is_some()
+        if crossing
+        && kerb_side.intersection(vehicle.body).is_some()
         && (self.direction == Direction::Left || self.direction == Direction::Right)
         && self.body.intersection(sector.in_intersect).is_some()
         && !(
@@ -211,9 +317,14 @@ impl Vehicle {
             || (self.route == Direction::East && vehicle.route == Direction::West)
             || (self.route == Direction::West && vehicle.route == Direction::East)
         ){
+            self.stop_reason = Some(StopReason::RightPriority(vehicle.id));
             return 1;
         }
 
+        // Short headways in a platoon: no slowing down behind the vehicles of the same platoon
+        if self.platoon.is_some() && vehicle.platoon == self.platoon {
+            continue;
+        }
         // Check collision between the front deceleration box and the body rectangle
         if self.hitbox.slowdown_1.intersection(vehicle.body).is_some(){
             return 3;
@@ -228,41 +339,53 @@ impl Vehicle {
 }
 
     pub fn turn(&mut self, sector: Sector)-> i32 {
-        // Define a map for route changes based on direction and sector centers
-        let turn_map = match self.route {
-            Direction::North => [
-                (self.direction == Direction::Left,sector.turn_north.0.center(),sector.turn_north.0.center().y - (self.position.y + self.velocity), Direction::West),
-                (self.direction == Direction::Right,sector.turn_north.1.center(),sector.turn_north.1.center().y - (self.position.y + self.velocity), Direction::East),
-            ],
-            Direction::South => [
-                (self.direction == Direction::Left,sector.turn_south.0.center(),(self.position.y - self.velocity) - sector.turn_south.0.center().y, Direction::East),
-                (self.direction == Direction::Right,sector.turn_south.1.center(),(self.position.y - self.velocity )- sector.turn_south.1.center().y, Direction::West),
-            ],
-            Direction::East => [
-                (self.direction == Direction::Left,sector.turn_east.0.center(),(self.position.x - self.velocity )- sector.turn_east.0.center().x, Direction::North),
-                (self.direction == Direction::Right,sector.turn_east.1.center(),(self.position.x - self.velocity) - sector.turn_east.1.center().x, Direction::South),
-            ],
-            Direction::West => [
-                (self.direction == Direction::Left,sector.turn_west.0.center(),sector.turn_west.0.center().x - (self.position.x + self.velocity), Direction::South),
-                (self.direction == Direction::Right,sector.turn_west.1.center(),sector.turn_west.1.center().x - (self.position.x + self.velocity), Direction::North),
-            ],
+        // Follow the waypoints of a roundabout, taking the route of each one when reaching it
+        if let Some(waypoint) = self.waypoints.front().copied() {
+            let (at, ahead) = match self.route {
+                Direction::North => (self.position.y == waypoint.point.y, waypoint.point.y - (self.position.y + self.velocity)),
+                Direction::South => (self.position.y == waypoint.point.y, (self.position.y - self.velocity) - waypoint.point.y),
+                Direction::East => (self.position.x == waypoint.point.x, (self.position.x - self.velocity) - waypoint.point.x),
+                Direction::West => (self.position.x == waypoint.point.x, waypoint.point.x - (self.position.x + self.velocity)),
+                _ => return -1,
+            };
+            if at {
+                self.position = waypoint.point;
+                self.route = waypoint.route;
+                self.waypoints.pop_front();
+                if self.waypoints.is_empty() {
+                    self.direction = Direction::Forward;
+                }
+            } else if ahead < 0 {
+                return self.velocity + ahead;
+            }
+            return -1;
+        }
+        // Turn point of the lane the vehicle is on, for the turn it takes
+        let aligned = |point: Point| match self.route {
+            Direction::North | Direction::South => point.x == self.position.x,
+            _ => point.y == self.position.y,
+        };
+        let Some(turn) = sector.turns.iter().find(|t| t.route == self.route && t.turn == self.direction && aligned(t.point)) else {
+            return -1;
+        };
+        let distance = match self.route {
+            Direction::North => turn.point.y - (self.position.y + self.velocity),
+            Direction::South => (self.position.y - self.velocity) - turn.point.y,
+            Direction::East => (self.position.x - self.velocity) - turn.point.x,
+            Direction::West => turn.point.x - (self.position.x + self.velocity),
             _ => return 0,
         };
-    
-        // Check for turning conditions and update the route
-        for &(condition,center,distance, new_route) in &turn_map {
-            if condition && distance < 0 && !(self.body.center() == center)  {
-                return self.velocity + distance
-            } 
-            if condition && self.body.center() == center {
-                self.route = new_route;
-                self.direction = Direction::Forward;
-                break;
-            }
+        if self.body.center() == turn.point {
+            self.route = turn.to;
+            self.direction = Direction::Forward;
+        } else if distance < 0 {
+            return self.velocity + distance
         }
         return -1
     }
-    pub fn forward(&mut self,vehicles:VecDeque<Vehicle>, sector:Sector, turn_velocity:i32){
+    pub fn forward(&mut self,vehicles:VecDeque<Vehicle>, sector:Sector, turn_velocity:i32, now: Duration){
+        let was_moving = self.velocity > 0;
+        let in_entry = self.body.has_intersection(sector.entry_intersect);
         // if a car are front of the vehicle
         match self.is_overlapping(sector.clone(),vehicles.clone()){
             1 => self.speed = Velocity::Stop,
@@ -302,9 +425,9 @@ impl Vehicle {
         };
         // Velocity:: with acceleration
         if acctual_speed > self.velocity as u32{
-            if Instant::now().duration_since(self.last_acceleration) >= self.acceleration_delay{
+            if now.saturating_sub(self.last_acceleration) >= self.acceleration_delay{
                 self.velocity += 1;
-                self.last_acceleration = Instant::now();
+                self.last_acceleration = now;
             }
         }else{
             self.velocity = acctual_speed as i32;
@@ -313,6 +436,17 @@ impl Vehicle {
         if turn_velocity >= 0{
             self.velocity = turn_velocity;
         }
+        // stop served at the line of a stop sign
+        if self.velocity == 0 && self.stopped_at.is_none() && sector.control.stops(self.route) && at_line(self, &sector) {
+            self.stopped_at = Some(now);
+        }
+        // count the stops and the time spent stopped
+        if self.velocity == 0 {
+            if was_moving {
+                self.stats.stops += 1;
+            }
+            self.stats.stopped_time += TICK;
+        }
         // update position with the velocity
         self.position = match self.route{ 
             Direction::North => Point::new(self.position.x(), self.position.y + self.velocity),
@@ -323,23 +457,116 @@ impl Vehicle {
         };
 
         // recalculate hitboxes
-        let hitboxes = cal_hitboxes(self.position, self.route);
+        let hitboxes = side_hitboxes(self.position, self.route, self.side);
         self.body = hitboxes.0;
         self.hitbox = hitboxes.1;
 
         // Update stats
-        self.stats.time = Instant::now() - self.id;
+        self.stats.time = now.saturating_sub(self.spawn_time);
         self.stats.distance += self.velocity as u32;
+        if in_entry {
+            self.stats.box_distance += self.velocity as u32;
+        }
 
     }
+    // Rebuild a vehicle from its recorded state, used to draw recorded runs
+    pub fn from_state(state: &VehicleState) -> Self {
+        let mut vehicle = Vehicle::new(state.id, state.route, state.direction, state.class as usize, Duration::ZERO);
+        vehicle.position = Point::new(state.x, state.y);
+        vehicle.velocity = state.velocity;
+        let (body, hitbox) = side_hitboxes(vehicle.position, vehicle.route, vehicle.side);
+        vehicle.body = body;
+        vehicle.hitbox = hitbox;
+        vehicle
+    }
+    // State of the vehicle as written in run recordings
+    pub fn state(&self) -> VehicleState {
+        VehicleState {
+            id: self.id,
+            class: self.class as u8,
+            route: self.route,
+            direction: self.direction,
+            x: self.position.x,
+            y: self.position.y,
+            velocity: self.velocity,
+        }
+    }
     pub fn arrival(&mut self){
         // calculate the medium velocity from distance and time passed
         // Store the stats
         self.stats.velocity = (self.stats.distance as f64 / self.stats.time.as_secs_f64()).round() as u32;
+        // free flow: the same path at full speed, but at Normal speed around the intersection like `forward` drives it
+        let outside = self.stats.distance - self.stats.box_distance;
+        let free_flow = TICK * (outside / SPEED_V.2 + self.stats.box_distance / SPEED_V.1);
+        self.stats.control_delay = (self.stats.queue_delay + self.stats.time).saturating_sub(free_flow);
         // // print the stats
         // println!("time: {}s\ndistance: {}px\nvelocity: {}px/s\n", self.stats.time.as_secs_f64(), self.stats.distance, self.stats.velocity);
     }
 }
+// Distance from the centre line of the road to the middle of lane `lane`, lane 0 being next to the centre line
+fn lane_distance(lane: usize) -> i32 {
+    let width = (ROAD_WIDTH / (ROAD_NUMBER * 2)) as i32;
+    let outer = (ROAD_WIDTH / 2) as i32 - width / 2;
+    outer - (ROAD_NUMBER as i32 - 1 - lane as i32) * width
+}
+// Lane of a turn on the arms of a crossing: Left next to the centre line, Forward, Right by the kerb
+pub fn default_lane(direction: Direction) -> usize {
+    match direction {
+        Direction::Left => 0,
+        Direction::Forward => 1,
+        _ => 2,
+    }
+}
+// Point on lane `lane` of the road of `route` to an intersection centred on `center`, driving on `side`,
+// as far along the road as `position`
+pub fn lane_point(center: Point, route: Direction, lane: usize, side: DrivingSide, position: Point) -> Point {
+    let distance = lane_distance(lane) * side.sign();
+    match route {
+        Direction::North => Point::new(center.x - distance, position.y),
+        Direction::South => Point::new(center.x + distance, position.y),
+        Direction::East => Point::new(position.x, center.y - distance),
+        Direction::West => Point::new(position.x, center.y + distance),
+        _ => position,
+    }
+}
+// Point on the lane of `direction` of the road of `route` on a crossing, as far along the road as `position`
+pub fn lane_position(center: Point, route: Direction, direction: Direction, side: DrivingSide, position: Point) -> Point {
+    lane_point(center, route, default_lane(side.mirror(direction)), side, position)
+}
+// Lane `position` is on, on the road of `route` to an intersection centred on `center`
+pub fn lane_at(center: Point, route: Direction, side: DrivingSide, position: Point) -> usize {
+    let gap = |lane: usize| {
+        let point = lane_point(center, route, lane, side, position);
+        (point.x - position.x).abs() + (point.y - position.y).abs()
+    };
+    (0..ROAD_NUMBER as usize).min_by_key(|&lane| gap(lane)).unwrap()
+}
+// Where a vehicle of `route` on lane `lane` enters the tile of an intersection, just outside of it
+pub fn entry_position(tile: Rect, route: Direction, lane: usize, side: DrivingSide) -> Point {
+    let half_vehicle_height = VEHICLE_HEIGHT as i32 / 2;
+    let edge = match route {
+        Direction::North => Point::new(0, tile.top() - half_vehicle_height),
+        Direction::South => Point::new(0, tile.bottom() + half_vehicle_height),
+        Direction::East => Point::new(tile.right() + half_vehicle_height, 0),
+        Direction::West => Point::new(tile.left() - half_vehicle_height, 0),
+        _ => todo!(),
+    };
+    lane_point(tile.center(), route, lane, side, edge)
+}
+
+// Body and hitboxes of a vehicle driving on `side`: the side hitboxes of right-hand traffic are mirrored
+// across the vehicle when driving on the left
+fn side_hitboxes(position: Point, route: Direction, side: DrivingSide) -> (Rect, Hitbox) {
+    let (body, mut hitbox) = cal_hitboxes(position, route);
+    if side == DrivingSide::Left {
+        let mirror = |r: Rect| match route {
+            Direction::North | Direction::South => Rect::new(2 * position.x - r.right(), r.y(), r.width(), r.height()),
+            _ => Rect::new(r.x(), 2 * position.y - r.bottom(), r.width(), r.height()),
+        };
+        (hitbox.left, hitbox.right) = (mirror(hitbox.right), mirror(hitbox.left));
+    }
+    (body, hitbox)
+}
 // ToDo: Optimize and add hitbox for some cases like:
 // - stop av before to avoid stopping all traffics
 // - side detection more exported on external side
@@ -0,0 +1,296 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use crate::demand::Spawn;
+use crate::heatmap::{Conflict, Heatmap, HARD_STOP_VELOCITY};
+use crate::network::Network;
+use crate::platoon::{self, PlatoonConfig, PlatoonStats};
+use crate::queue::{EntryQueues, QueuedVehicle};
+use crate::replay::SpawnEvent;
+use crate::safety::{SafetyConfig, SafetyMonitor};
+use crate::vehicle::{default_lane, lane_at, Stats, Vehicle, VEHICLE_CLASSES};
+use crate::{Direction, Sector, NUMBER_AV, TICK};
+
+// Ticks between two samples of the time series
+pub const SAMPLE_TICKS: u32 = 100;
+
+// State of the world at a sampling time
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, Debug, PartialEq, Copy)]
+pub struct Sample {
+    pub time: Duration,
+    pub arrived: usize,         // vehicles arrived since the start
+    pub active: usize,          // vehicles on the map
+    pub in_intersection: usize, // vehicles inside an intersection box
+    pub queued: usize,          // vehicles waiting in the entry queues
+}
+
+// Seed of the `n`-th stream drawn from `seed`, the streams of two seeds or two numbers being unrelated
+pub fn stream_seed(seed: u64, n: u64) -> u64 {
+    seed.rotate_left(32) ^ n.wrapping_mul(0x9E37_79B9_7F4A_7C15)
+}
+
+// State of the simulated intersections, advanced one tick at a time without any rendering
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, Debug)]
+pub struct World {
+    pub vehicles: VecDeque<Vehicle>,
+    pub queues: EntryQueues,
+    pub network: Network,       // intersections and the roads between them
+    pub stats: VecDeque<Stats>,
+    pub series: Vec<Sample>,
+    pub safety: SafetyMonitor,
+    pub heatmap: Heatmap,       // where the close calls, collisions and hard stops happened
+    pub platooning: Option<PlatoonConfig>,  // connected vehicles forming platoons, None for independent vehicles
+    pub platoons: PlatoonStats,
+    pub tick: u32,      // number of ticks simulated
+    pub seed: u64,      // seed of the vehicle colours and paths
+    next_id: u32,
+    spawned: u64,       // vehicles spawned, each one draws from its own stream of the seed
+}
+impl World {
+    pub fn new(seed: u64) -> Self {
+        let network = Network::default();
+        World {
+            vehicles: VecDeque::new(),
+            queues: EntryQueues::new(),
+            safety: SafetyMonitor::new(&network, SafetyConfig::default()),
+            heatmap: Heatmap::new(network.map),
+            network,
+            platooning: None,
+            platoons: PlatoonStats::default(),
+            stats: VecDeque::new(),
+            series: Vec::new(),
+            tick: 0,
+            seed,
+            next_id: 0,
+            spawned: 0,
+        }
+    }
+    // Use other thresholds for the surrogate safety measures
+    pub fn with_safety(mut self, config: SafetyConfig) -> Self {
+        self.safety.config = config;
+        self
+    }
+    // Simulate a network of intersections instead of the single one
+    pub fn with_network(mut self, network: Network) -> Self {
+        self.safety = SafetyMonitor::new(&network, self.safety.config);
+        self.heatmap = Heatmap::new(network.map);
+        self.network = network;
+        self
+    }
+    // Simulation time elapsed since the start
+    pub fn clock(&self) -> Duration {
+        TICK * self.tick
+    }
+
+    // Queue a new vehicle with a random colour, returns the event to record
+    // on a network it enters at one of the intersections open to its route and follows a random path
+    // None when no intersection has an arm for the route or a lane for the turn (e.g. toward the missing arm of a T-junction)
+    pub fn spawn(&mut self, route: Direction, direction: Direction) -> Option<SpawnEvent> {
+        // no generator state to save in snapshots, the n-th vehicle always draws the same values
+        let mut rng = StdRng::seed_from_u64(stream_seed(self.seed, self.spawned));
+        self.spawned += 1;
+        let class = rng.gen_range(0..VEHICLE_CLASSES.len()) as u8;
+        let entries: Vec<usize> = self
+            .network
+            .entries(route)
+            .into_iter()
+            .filter(|&node| self.network.nodes[node].sector.layout.allows(route, direction))
+            .collect();
+        let node = match entries.len() {
+            0 => return None,
+            1 => entries[0],
+            n => entries[rng.gen_range(0..n)],
+        };
+        let path = self.network.random_path(node, route, direction, &mut rng);
+        let event = SpawnEvent {
+            tick: self.tick,
+            route,
+            direction,
+            class,
+            node: node as u16,
+            path,
+        };
+        self.push(&event);
+        Some(event)
+    }
+    // Queue a recorded vehicle on a lane of its turn, the one with the shortest queue
+    pub fn push(&mut self, event: &SpawnEvent) {
+        let spawn = Spawn {
+            route: event.route,
+            direction: event.direction,
+        };
+        let node = event.node as usize;
+        let layout = &self.network.nodes[node].sector.layout;
+        let lanes = layout.lanes_for(spawn.route, spawn.direction);
+        let lane = lanes
+            .into_iter()
+            .min_by_key(|&lane| self.queues.lane_len(node, spawn.route, lane))
+            .unwrap_or_else(|| default_lane(layout.side.mirror(spawn.direction)));
+        self.queues.push(QueuedVehicle {
+            spawn,
+            class: event.class as usize,
+            since: self.clock(),
+            node,
+            lane,
+            path: event.path.clone(),
+        });
+    }
+
+    // Advance the simulation by one tick
+    pub fn step(&mut self) {
+        self.release_queues();
+        let now = self.clock();
+        if let Some(config) = self.platooning {
+            let sectors: Vec<&Sector> = self.network.nodes.iter().map(|n| &n.sector).collect();
+            platoon::form(self.vehicles.make_contiguous(), &sectors, config);
+        }
+        for i in 0..self.vehicles.len() {
+            let vehicles = self.vehicles.clone();
+            let sector = self.network.nodes[self.vehicles[i].node].sector.clone();
+            // turn if vehicle need
+            let velocity =self.vehicles[i].turn(sector.clone());
+            let before = (self.vehicles[i].stats, self.vehicles[i].velocity);
+            // move forward if vehicle can
+            self.vehicles[i].forward(vehicles.clone(),sector,velocity,now);
+            self.record_conflicts(i, before);
+            self.hand_off(i);
+        }
+        // Measure the conflicts between the vehicles at their new positions
+        let moved: Vec<&Vehicle> = self.vehicles.iter().collect();
+        self.safety.observe(&moved, now);
+        // Entries into the boxes, for the platoon sizes and the headways
+        let entering: Vec<usize> = (0..self.vehicles.len())
+            .filter(|&i| {
+                let v = &self.vehicles[i];
+                !v.entered && v.body.has_intersection(self.network.nodes[v.node].sector.in_intersect)
+            })
+            .collect();
+        for i in entering {
+            let all: Vec<&Vehicle> = self.vehicles.iter().collect();
+            self.platoons.enter(all[i], &all, now);
+            self.vehicles[i].entered = true;
+        }
+        // Retain only the vehicles that have not yet arrived, out of the tile of their last intersection
+        let network = &self.network;
+        let stats = &mut self.stats;
+        self.vehicles.retain(|vehicle| {
+            let tile = network.tile(vehicle.node);
+            let arrived =
+            vehicle.body.intersection(tile).is_none()
+            && vehicle.hitbox.urgency_stop.intersection(tile).is_none();
+
+            if arrived {
+                let mut t_av = vehicle.clone();
+                t_av.arrival();
+                t_av.stats.arrival = now;
+                stats.push_back(t_av.stats);
+            }
+
+            !arrived
+        });
+        self.tick += 1;
+        if self.tick.is_multiple_of(SAMPLE_TICKS) {
+            self.sample();
+        }
+    }
+
+    // Move a vehicle leaving the tile of its intersection on a road to the next one,
+    // into the lane of its next turn, or on its current lane (and turn) when that one is taken
+    fn hand_off(&mut self, i: usize) {
+        let vehicle = &self.vehicles[i];
+        if !self.network.has_left(vehicle.node, vehicle.route, vehicle.position) {
+            return;
+        }
+        let Some(next) = self.network.next(vehicle.node, vehicle.route) else { return };
+        let center = self.network.center(next);
+        let sector = self.network.nodes[next].sector.clone();
+        let mut moved = vehicle.clone();
+        let turn = moved.path.pop_front().unwrap_or(Direction::Forward);
+        let lanes = sector.layout.lanes_for(moved.route, turn);
+        if !lanes.is_empty() {
+            moved.enter_node(next, center, turn, lanes[moved.id() as usize % lanes.len()]);
+            self.plan_ring(&mut moved);
+        }
+        if lanes.is_empty() || moved.clone().is_overlapping(sector.clone(), self.vehicles.clone()) == 1 {
+            // stay on the current lane, or the nearest one, and take a turn of its own
+            moved = self.vehicles[i].clone();
+            moved.path.pop_front();
+            let lane = lane_at(center, moved.route, moved.side, moved.position);
+            let mut nearest: Vec<usize> = (0..sector.layout.arm(moved.route).map_or(0, |arm| arm.lanes.len())).collect();
+            nearest.sort_by_key(|&l| l.abs_diff(lane));
+            let Some((lane, turn)) = nearest
+                .into_iter()
+                .find_map(|l| sector.layout.turns_of(moved.route, l).first().map(|&turn| (l, turn)))
+            else {
+                return;
+            };
+            moved.enter_node(next, center, turn, lane);
+            self.plan_ring(&mut moved);
+        }
+        self.vehicles[i] = moved;
+    }
+    // Route round the roundabout of the intersection a vehicle enters, if it has one
+    fn plan_ring(&self, vehicle: &mut Vehicle) {
+        vehicle.waypoints = match &self.network.nodes[vehicle.node].sector.roundabout {
+            Some(ring) => ring.waypoints(vehicle.route, vehicle.direction, vehicle.position),
+            None => VecDeque::new(),
+        };
+    }
+
+    // Add the conflicts of a vehicle during its last move to the heatmap
+    fn record_conflicts(&mut self, i: usize, (stats, velocity): (Stats, i32)) {
+        let vehicle = &self.vehicles[i];
+        if vehicle.stats.close_call > stats.close_call {
+            self.heatmap.add(vehicle.position, Conflict::CloseCall);
+        }
+        if vehicle.stats.colision > stats.colision {
+            self.heatmap.add(vehicle.position, Conflict::Collision);
+        }
+        if velocity > HARD_STOP_VELOCITY && vehicle.velocity == 0 {
+            self.heatmap.add(vehicle.position, Conflict::HardStop);
+        }
+    }
+
+    // Add the current state to the time series
+    fn sample(&mut self) {
+        let network = &self.network;
+        self.series.push(Sample {
+            time: self.clock(),
+            arrived: self.stats.len(),
+            active: self.vehicles.len(),
+            in_intersection: self.vehicles
+                .iter()
+                .filter(|v| network.in_intersection(v.body))
+                .count(),
+            queued: self.queues.len(),
+        });
+    }
+
+    // Move the head of each entry queue onto the map when its lane is free
+    fn release_queues(&mut self) {
+        let now = self.clock();
+        for queued in self.queues.fronts() {
+            if self.vehicles.len() >= NUMBER_AV * self.network.nodes.len() {
+                break;
+            }
+            let tile = self.network.tile(queued.node);
+            let side = self.network.nodes[queued.node].sector.layout.side;
+            let mut vehicle = Vehicle::new(self.next_id, queued.spawn.route, queued.spawn.direction, queued.class, now)
+                .with_side(side)
+                .with_path(queued.node, tile, queued.lane, queued.path);
+            self.plan_ring(&mut vehicle);
+            let sector = self.network.nodes[queued.node].sector.clone();
+            let is_overlapping = vehicle.is_overlapping(sector,self.vehicles.clone());
+            if is_overlapping == 0 {
+                self.queues.pop(queued.node, queued.spawn.route, queued.lane);
+                vehicle.stats.queue_delay = now - queued.since;
+                self.vehicles.push_back(vehicle);
+                self.next_id += 1;
+            }
+        }
+    }
+}
@@ -0,0 +1,120 @@
+// Minimal reader of the XML files of other tools (OpenStreetMap, SUMO):
+// elements and their attributes only, text, comments and declarations are skipped
+
+// Start or end of an element, an empty element gives both
+#[derive(Clone, Debug, PartialEq)]
+pub enum XmlEvent {
+    Start { name: String, attributes: Vec<(String, String)> },
+    End { name: String },
+}
+
+// Value of an attribute of a start event
+pub fn attribute<'a>(attributes: &'a [(String, String)], key: &str) -> Option<&'a str> {
+    attributes.iter().find(|(k, _)| k == key).map(|(_, v)| v.as_str())
+}
+
+// Every element of a document, in order
+pub fn events(text: &str) -> Result<Vec<XmlEvent>, String> {
+    let mut events = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find('<') {
+        rest = &rest[start..];
+        // comments, CDATA, declarations and processing instructions
+        let skip = [("<!--", "-->"), ("<![CDATA[", "]]>"), ("<?", "?>"), ("<!", ">")]
+            .into_iter()
+            .find(|(open, _)| rest.starts_with(open));
+        if let Some((_, close)) = skip {
+            let end = rest.find(close).ok_or("unterminated XML declaration")?;
+            rest = &rest[end + close.len()..];
+            continue;
+        }
+        let end = tag_end(rest).ok_or("unterminated XML tag")?;
+        let tag = &rest[1..end];
+        rest = &rest[end + 1..];
+        if let Some(name) = tag.strip_prefix('/') {
+            events.push(XmlEvent::End { name: name.trim().to_string() });
+            continue;
+        }
+        let (tag, empty) = match tag.strip_suffix('/') {
+            Some(tag) => (tag, true),
+            None => (tag, false),
+        };
+        let name_end = tag.find(char::is_whitespace).unwrap_or(tag.len());
+        let name = tag[..name_end].to_string();
+        let attributes = parse_attributes(&tag[name_end..])?;
+        events.push(XmlEvent::Start { name: name.clone(), attributes });
+        if empty {
+            events.push(XmlEvent::End { name });
+        }
+    }
+    Ok(events)
+}
+
+// Position of the '>' closing the tag at the start of `text`, quotes skipped
+fn tag_end(text: &str) -> Option<usize> {
+    let mut quote = None;
+    for (i, c) in text.char_indices() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(q), _) if q == c => quote = None,
+            (None, '>') => return Some(i),
+            _ => {}
+        }
+    }
+    None
+}
+
+// key="value" pairs of a tag
+fn parse_attributes(mut text: &str) -> Result<Vec<(String, String)>, String> {
+    let mut attributes = Vec::new();
+    loop {
+        text = text.trim_start();
+        if text.is_empty() {
+            return Ok(attributes);
+        }
+        let equal = text.find('=').ok_or_else(|| format!("attribute without value: {}", text))?;
+        let key = text[..equal].trim().to_string();
+        text = text[equal + 1..].trim_start();
+        let quote = text.chars().next().filter(|c| *c == '"' || *c == '\'').ok_or("unquoted XML attribute")?;
+        let end = text[1..].find(quote).ok_or("unterminated XML attribute")? + 1;
+        attributes.push((key, unescape(&text[1..end])));
+        text = &text[end + 1..];
+    }
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn elements_and_attributes() {
+        let text = r#"<?xml version="1.0"?>
+<!-- comment -->
+<net version='1.9'><edge id="a&amp;b" to=">"/>text</net>"#;
+        let attributes = |pairs: &[(&str, &str)]| pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        assert_eq!(
+            events(text).unwrap(),
+            vec![
+                XmlEvent::Start { name: "net".to_string(), attributes: attributes(&[("version", "1.9")]) },
+                XmlEvent::Start { name: "edge".to_string(), attributes: attributes(&[("id", "a&b"), ("to", ">")]) },
+                XmlEvent::End { name: "edge".to_string() },
+                XmlEvent::End { name: "net".to_string() },
+            ]
+        );
+    }
+
+    #[test]
+    fn unterminated() {
+        assert!(events("<net id=\"a").is_err());
+        assert!(events("<!-- comment").is_err());
+    }
+}